@@ -0,0 +1,1153 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{solver, topology::Topology};
+
+// Most mines one cell can hold in multi-mine game
+pub const MULTI_MINES: u8 = 3;
+
+// Biggest board custom size fields in HUD allow.
+// Saves and replays with bigger ones are broken
+pub const MAX_WIDTH: usize = 40;
+pub const MAX_HEIGHT: usize = 30;
+
+// One square of the board. Board knows nothing
+// about godot, so CellGrid reads these to decide
+// what every Cell should look like
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tile {
+    // Amount of mines in the cell. Only multi-mine
+    // boards can have more than 1 mine in a cell
+    pub mines: u8,
+    pub is_opened: bool,
+    // Flags player put on the cell, same as
+    // mines there can be more than 1
+    pub flags: u8,
+    // Question mark is only a note for player.
+    // It's not a flag and opening cell removes it
+    pub is_questioned: bool,
+    pub mines_around: u8,
+}
+
+impl Tile {
+    pub fn is_mine(&self) -> bool {
+        self.mines > 0
+    }
+
+    pub fn is_flagged(&self) -> bool {
+        self.flags > 0
+    }
+
+    // Mine player opened. In lives mode game goes on
+    // and it stays opened
+    pub fn is_exploded(&self) -> bool {
+        self.is_opened && self.is_mine()
+    }
+
+    // Flags that don't match mines under them: any
+    // flag on a safe cell, or too few or too many
+    // on a multi-mine one. Shown after game is lost
+    pub fn has_wrong_flags(&self) -> bool {
+        self.is_flagged() && self.flags != self.mines
+    }
+
+    // Mines player knows about: flags, or mines
+    // of exploded cell which don't need a flag any more
+    pub fn known_mines(&self) -> u8 {
+        if self.is_exploded() {
+            self.mines
+        } else {
+            self.flags
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Playing,
+    Won,
+    Lost,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reveal {
+    // Cell is already opened, flagged
+    // or game is over. Nothing changed
+    Ignored,
+    // Player opened a mine on this position
+    // and has no lives left
+    Exploded((usize, usize)),
+    // Player opened mines but had lives for them,
+    // other cells were opened as usual
+    Survived {
+        exploded: Vec<(usize, usize)>,
+        opened: Vec<(usize, usize)>,
+    },
+    // Every cell that was opened by this click
+    // in order they were opened
+    Opened(Vec<(usize, usize)>),
+}
+
+// What is guaranteed about the first cell player opens.
+// Mines are placed only after first click, so it
+// can never be a mine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FirstClick {
+    // Only clicked cell is free of mines
+    #[default]
+    Safe,
+    // Clicked cell and cells around it are free of mines
+    // so first click always opens some area
+    Opening,
+}
+
+// Limits for no guess generation. Dense boards rarely
+// come out solvable, so generator gives up when
+// attempts run out and keeps the last board.
+// There is no time limit, so same seed gives
+// same board no matter how fast the machine is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoGuess {
+    pub attempts: u32,
+}
+
+// Why board with given size can't be created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeError {
+    Empty,
+    TooManyMines,
+}
+
+impl std::fmt::Display for SizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SizeError::Empty => write!(f, "Board has no cells"),
+            SizeError::TooManyMines => write!(f, "Too many mines"),
+        }
+    }
+}
+
+// Plain rust model of the game.
+// All rules live here so they can be tested
+// with cargo test without running godot
+#[derive(Debug, Clone)]
+pub struct Board {
+    // Board is stored row by row, so x is a row
+    // and goes up to height, y is a column
+    width: usize,
+    height: usize,
+    // Total amount of mines, not cells with them
+    mines_amount: usize,
+    // Cells with at least one mine. Game is won
+    // when every other cell is opened
+    mined_cells: usize,
+    // Most mines one cell can hold, 1 on classic boards
+    max_mines: u8,
+    // Every opened mine takes a life, game is lost
+    // when all of them are gone. Classic board has 1
+    lives: u32,
+    lives_lost: u32,
+    tiles: Vec<Vec<Tile>>,
+    cells_opened: usize,
+    flags: i32,
+    status: Status,
+
+    // Which cells are around each other.
+    // Numbers and flood fill follow it
+    topology: Topology,
+
+    // Mines are placed on first reveal
+    // using this seed and first_click rule.
+    // Same seed and same first click give same board
+    seed: u64,
+    first_click: FirstClick,
+    mines_placed: bool,
+    // Cell random mines were placed around. None before
+    // that and for boards made from known mines
+    first_reveal: Option<(usize, usize)>,
+
+    // If set, only boards solver can finish
+    // from first click without guessing are accepted
+    no_guess: Option<NoGuess>,
+}
+
+impl Board {
+    // Creates board with mines on exact positions.
+    // Positions outside of the board are ignored
+    pub fn new(width: usize, height: usize, mines: &HashSet<(usize, usize)>) -> Self {
+        let mines = mines.iter().map(|&mine| (mine, 1)).collect();
+        Self::with_mines(width, height, 1, &mines)
+    }
+
+    // Same as new, but mines has amount of mines for
+    // every cell with them, up to max_mines in one cell
+    pub fn with_mines(width: usize, height: usize, max_mines: u8, mines: &HashMap<(usize, usize), u8>) -> Self {
+        let mut board = Self::pending(width, height, 0, 0, FirstClick::Safe);
+        board.max_mines = max_mines.max(1);
+        board.place_mines(mines);
+        board
+    }
+
+    // Creates board without mines. They will be placed
+    // on first reveal, away from clicked cell.
+    // At least one cell has to stay free so
+    // mines_amount is limited to width * height - 1
+    pub fn pending(width: usize, height: usize, mines_amount: usize, seed: u64, first_click: FirstClick) -> Self {
+        let mines_amount = mines_amount.min((width * height).saturating_sub(1));
+
+        Self {
+            width,
+            height,
+            mines_amount,
+            mined_cells: 0,
+            max_mines: 1,
+            lives: 1,
+            lives_lost: 0,
+            tiles: vec![vec![Tile::default(); width]; height],
+            cells_opened: 0,
+            // Amount of flags should be equal to mines themselves
+            flags: mines_amount as i32,
+            status: Status::Playing,
+            topology: Topology::SQUARE,
+            seed,
+            first_click,
+            mines_placed: false,
+            first_reveal: None,
+            no_guess: None,
+        }
+    }
+
+    // Has to be called before first reveal to take effect
+    pub fn set_no_guess(&mut self, no_guess: Option<NoGuess>) {
+        self.no_guess = no_guess;
+    }
+
+    // Lets random mines stack up to max_mines in one cell.
+    // Amount of mines stays the same, so there are
+    // less cells with mines. Has to be called before first reveal
+    pub fn set_max_mines(&mut self, max_mines: u8) {
+        self.max_mines = max_mines.max(1);
+    }
+
+    // Lets player survive lives - 1 mines. Has to be
+    // called before first reveal, same as other rules
+    pub fn set_lives(&mut self, lives: u32) {
+        self.lives = lives.max(1);
+    }
+
+    // Numbers depend on topology, so board
+    // with mines already has them counted again
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+        if self.mines_placed {
+            let mines = self.mines();
+            self.place_mines(&mines);
+        }
+    }
+
+    // Recreates board from saved game. Only mines, is_opened, flags
+    // and is_questioned of tiles are used, everything else is counted again.
+    // Lives lost are exploded cells. Mines are treated as placed already
+    pub fn restore(seed: u64, topology: Topology, max_mines: u8, lives: u32, tiles: &[Vec<Tile>]) -> Self {
+        let height = tiles.len();
+        let width = tiles.first().map_or(0, Vec::len);
+        let mut board = Self::new(width, height, &HashSet::new());
+        board.topology = topology;
+        board.max_mines = max_mines.max(1);
+        board.lives = lives.max(1);
+        let mines = board.positions()
+            .filter(|(x, y)| tiles[*x][*y].is_mine())
+            .map(|(x, y)| ((x, y), tiles[x][y].mines))
+            .collect();
+        board.place_mines(&mines);
+        board.seed = seed;
+
+        for (x, y) in board.positions().collect::<Vec<_>>() {
+            let tile = &mut board.tiles[x][y];
+            tile.is_opened = tiles[x][y].is_opened;
+            tile.flags = tiles[x][y].flags;
+            tile.is_questioned = tiles[x][y].is_questioned;
+
+            if tile.is_exploded() {
+                board.lives_lost += 1;
+            } else if tile.is_opened {
+                board.cells_opened += 1;
+            }
+            board.flags -= tile.known_mines() as i32;
+        }
+
+        board
+    }
+
+    // Checks custom game settings before creating board.
+    // At least one cell has to stay free of mines
+    pub fn check_size(width: usize, height: usize, mines_amount: usize) -> Result<(), SizeError> {
+        if width == 0 || height == 0 {
+            return Err(SizeError::Empty);
+        }
+        if mines_amount >= width * height {
+            return Err(SizeError::TooManyMines);
+        }
+        Ok(())
+    }
+
+    // Same layout and rules with every cell closed again,
+    // so game can be played from the start on a copy
+    pub fn fresh(&self) -> Self {
+        let mut board = Self::pending(self.width, self.height, 0, self.seed, self.first_click);
+        board.topology = self.topology;
+        board.max_mines = self.max_mines;
+        board.lives = self.lives;
+        board.place_mines(&self.mines());
+        board
+    }
+
+    // Amount of mines in every cell that has them
+    fn mines(&self) -> HashMap<(usize, usize), u8> {
+        self.positions()
+            .filter(|(x, y)| self.tiles[*x][*y].is_mine())
+            .map(|(x, y)| ((x, y), self.tiles[x][y].mines))
+            .collect()
+    }
+
+    fn place_mines(&mut self, mines: &HashMap<(usize, usize), u8>) {
+        let (width, height) = (self.width, self.height);
+        self.mines_amount = 0;
+        self.mined_cells = 0;
+
+        // Generator can try several layouts on the same board
+        // so previous one has to be cleaned up first
+        for tile in self.tiles.iter_mut().flatten() {
+            tile.mines = 0;
+            tile.mines_around = 0;
+        }
+
+        for (&(x, y), &mines) in mines.iter().filter(|((x, y), mines)| *x < height && *y < width && **mines > 0) {
+            let mines = mines.min(self.max_mines);
+            self.tiles[x][y].mines = mines;
+            self.mines_amount += mines as usize;
+            self.mined_cells += 1;
+
+            // For every mine we increase mines_around
+            // field of cells around it, so cell
+            // with 3 mines adds 3
+            for (nx, ny) in self.neighbours(x, y) {
+                self.tiles[nx][ny].mines_around += mines;
+            }
+        }
+
+        // Player could place flags before first click
+        // so they have to be counted again
+        let flagged: usize = self.tiles.iter().flatten().map(|tile| tile.flags as usize).sum();
+        self.flags = self.mines_amount as i32 - flagged as i32;
+        self.mines_placed = true;
+    }
+
+    // Places mines_amount mines on random positions
+    // except (x, y) and cells around it if first_click asks for it
+    fn place_random_mines(&mut self, x: usize, y: usize) {
+        self.first_reveal = Some((x, y));
+        let mut safe = vec![(x, y)];
+
+        // Boards without guessing need something to start
+        // deducing from, so they always start with opening
+        if self.first_click == FirstClick::Opening || self.no_guess.is_some() {
+            safe.extend(self.neighbours(x, y));
+        }
+
+        // On small or very dense boards there is no space
+        // for opening, so only clicked cell stays safe
+        if (self.width * self.height - safe.len()) * (self.max_mines as usize) < self.mines_amount {
+            safe.truncate(1);
+        }
+
+        // ChaCha is used instead of thread_rng because
+        // its output is guaranteed to stay the same
+        // between rand versions and platforms
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let mut attempts = 0;
+
+        loop {
+            let mut mines = HashMap::new();
+            let mut placed = 0;
+
+            while placed < self.mines_amount {
+                // Generating positions x and y for mines.
+                // Full cells are skipped, so on classic boards
+                // no cell gets 2 mines.
+                // Generating u32 instead of usize so the same seed
+                // gives the same board on 32 and 64 bit machines
+                let x = rng.gen_range(0..self.height as u32) as usize;
+                let y = rng.gen_range(0..self.width as u32) as usize;
+                if safe.contains(&(x, y)) {
+                    continue;
+                }
+                let cell_mines = mines.entry((x, y)).or_insert(0);
+                if *cell_mines < self.max_mines {
+                    *cell_mines += 1;
+                    placed += 1;
+                }
+            }
+
+            self.place_mines(&mines);
+            attempts += 1;
+
+            let Some(no_guess) = self.no_guess else {
+                break;
+            };
+            if solver::is_solvable(self, x, y) || attempts >= no_guess.attempts {
+                break;
+            }
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn mines_amount(&self) -> usize {
+        self.mines_amount
+    }
+
+    pub fn max_mines(&self) -> u8 {
+        self.max_mines
+    }
+
+    pub fn lives(&self) -> u32 {
+        self.lives
+    }
+
+    pub fn lives_left(&self) -> u32 {
+        self.lives - self.lives_lost
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn first_click(&self) -> FirstClick {
+        self.first_click
+    }
+
+    pub fn first_reveal(&self) -> Option<(usize, usize)> {
+        self.first_reveal
+    }
+
+    pub fn is_no_guess(&self) -> bool {
+        self.no_guess.is_some()
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    // False until first reveal places mines
+    pub fn is_started(&self) -> bool {
+        self.mines_placed
+    }
+
+    // Flags left to place. Can go below zero
+    // if player places more flags than there are mines
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+
+    pub fn cells_opened(&self) -> usize {
+        self.cells_opened
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn tile(&self, x: usize, y: usize) -> &Tile {
+        &self.tiles[x][y]
+    }
+
+    // Every position on the board row by row
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |x| (0..width).map(move |y| (x, y)))
+    }
+
+    // All valid positions around (x, y) for topology of the board.
+    // Positions outside of the board are skipped
+    pub fn neighbours(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> {
+        self.topology.neighbours(self.width, self.height, x, y)
+    }
+
+    pub fn reveal(&mut self, x: usize, y: usize) -> Reveal {
+        if self.status != Status::Playing {
+            return Reveal::Ignored;
+        }
+
+        let tile = self.tiles[x][y];
+        if tile.is_opened || tile.is_flagged() {
+            return Reveal::Ignored;
+        }
+
+        if !self.mines_placed {
+            self.place_random_mines(x, y);
+        }
+
+        self.open(vec![(x, y)])
+    }
+
+    // Opens all closed unflagged cells around opened number
+    // if player placed as many flags around it as number shows.
+    // If some flag is wrong player hits a mine
+    pub fn chord(&mut self, x: usize, y: usize) -> Reveal {
+        if self.status != Status::Playing {
+            return Reveal::Ignored;
+        }
+
+        let tile = self.tiles[x][y];
+        if !tile.is_opened || tile.is_exploded() || tile.mines_around == 0 {
+            return Reveal::Ignored;
+        }
+
+        // Exploded mines around count as flags
+        let flags: usize = self.neighbours(x, y)
+            .map(|(nx, ny)| self.tiles[nx][ny].known_mines() as usize)
+            .sum();
+        if flags != tile.mines_around as usize {
+            return Reveal::Ignored;
+        }
+
+        let closed: Vec<_> = self.neighbours(x, y)
+            .filter(|(nx, ny)| {
+                let tile = self.tiles[*nx][*ny];
+                !tile.is_opened && !tile.is_flagged()
+            })
+            .collect();
+        if closed.is_empty() {
+            return Reveal::Ignored;
+        }
+
+        self.open(closed)
+    }
+
+    // Opens every cell from cells and flood fills from empty ones.
+    // Every mine among them takes a life, when
+    // there are no lives left game is lost
+    fn open(&mut self, cells: Vec<(usize, usize)>) -> Reveal {
+        let (exploded, mut stack): (Vec<_>, Vec<_>) = cells.into_iter()
+            .partition(|(x, y)| self.tiles[*x][*y].is_mine());
+
+        for &(x, y) in exploded.iter() {
+            let tile = &mut self.tiles[x][y];
+            tile.is_opened = true;
+            tile.is_questioned = false;
+            self.lives_lost += 1;
+            if self.lives_lost == self.lives {
+                self.status = Status::Lost;
+                return Reveal::Exploded((x, y));
+            }
+
+            // Player knows where these mines are
+            // now, so they count as flagged
+            self.flags -= tile.mines as i32;
+        }
+
+        let mut opened = vec![];
+
+        while let Some((x, y)) = stack.pop() {
+            let tile = &mut self.tiles[x][y];
+            // Flags are not touched by flood fill,
+            // player has to remove them by hand
+            if tile.is_opened || tile.is_flagged() {
+                continue;
+            }
+            tile.is_opened = true;
+            tile.is_questioned = false;
+            opened.push((x, y));
+
+            // Need to keep track of opened cells
+            // as this game win condition relies on:
+            // total cells - mines == cells_opened
+            self.cells_opened += 1;
+
+            // If cell is not empty cell then continue
+            // and don't add cells around it to stack
+            // Only 1 cell with mines_around != 0
+            // is needed to be open
+            if tile.mines_around != 0 {
+                continue;
+            }
+
+            stack.extend(self.neighbours(x, y));
+        }
+
+        if self.cells_opened == self.width * self.height - self.mined_cells {
+            self.status = Status::Won;
+
+            // Only mines are left closed,
+            // so all of them get flags
+            for tile in self.tiles.iter_mut().flatten().filter(|tile| tile.is_mine() && !tile.is_opened) {
+                tile.flags = tile.mines;
+                tile.is_questioned = false;
+            }
+            self.flags = 0;
+        }
+
+        if exploded.is_empty() {
+            Reveal::Opened(opened)
+        } else {
+            Reveal::Survived { exploded, opened }
+        }
+    }
+
+    // Returns new flag state of the cell
+    // or None if cell can't be flagged.
+    // On multi-mine boards every call adds one more flag
+    // until there are max_mines of them, next one takes all away
+    pub fn toggle_flag(&mut self, x: usize, y: usize) -> Option<bool> {
+        if self.status != Status::Playing {
+            return None;
+        }
+
+        let tile = &mut self.tiles[x][y];
+        if tile.is_opened {
+            return None;
+        }
+
+        // Flags counter follows amount of mines, so
+        // cell with 3 flags takes 3 from it
+        let flags = if tile.flags < self.max_mines { tile.flags + 1 } else { 0 };
+        self.flags -= flags as i32 - tile.flags as i32;
+        tile.flags = flags;
+        tile.is_questioned = false;
+        Some(tile.is_flagged())
+    }
+
+    // Returns new question mark state of the cell or None
+    // if cell can't have it. Flag on the cell is replaced
+    pub fn toggle_question(&mut self, x: usize, y: usize) -> Option<bool> {
+        if self.status != Status::Playing {
+            return None;
+        }
+
+        let tile = &mut self.tiles[x][y];
+        if tile.is_opened {
+            return None;
+        }
+
+        self.flags += tile.flags as i32;
+        tile.flags = 0;
+        tile.is_questioned = !tile.is_questioned;
+        Some(tile.is_questioned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Neighbourhood;
+
+    fn board(size: usize, mines: &[(usize, usize)]) -> Board {
+        Board::new(size, size, &mines.iter().copied().collect())
+    }
+
+    #[test]
+    fn counts_mines_around() {
+        let board = board(3, &[(0, 0), (2, 2)]);
+        assert_eq!(board.tile(1, 1).mines_around, 2);
+        assert_eq!(board.tile(0, 1).mines_around, 1);
+        assert_eq!(board.tile(2, 0).mines_around, 0);
+        assert_eq!(board.flags(), 2);
+    }
+
+    #[test]
+    fn check_size_needs_free_cell() {
+        assert_eq!(Board::check_size(30, 16, 99), Ok(()));
+        assert_eq!(Board::check_size(0, 16, 0), Err(SizeError::Empty));
+        assert_eq!(Board::check_size(3, 2, 6), Err(SizeError::TooManyMines));
+        assert_eq!(Board::check_size(3, 2, 5), Ok(()));
+    }
+
+    #[test]
+    fn rectangular_board() {
+        // 2 rows and 4 columns
+        //  * 1 0 0
+        //  1 1 0 0
+        let mut board = Board::new(4, 2, &[(0, 0)].into_iter().collect());
+        assert_eq!(board.neighbours(1, 3).count(), 3);
+        assert_eq!(board.positions().count(), 8);
+        assert_eq!(board.tile(1, 1).mines_around, 1);
+
+        let Reveal::Opened(opened) = board.reveal(0, 3) else {
+            panic!("expected cells to open");
+        };
+        assert_eq!(opened.len(), 6);
+        assert!(!board.tile(1, 0).is_opened);
+
+        board.reveal(1, 0);
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn expert_board_places_all_mines() {
+        let mut board = Board::pending(30, 16, 99, 42, FirstClick::Opening);
+        board.reveal(15, 29);
+        let mines = mines(&board);
+        assert_eq!(mines.len(), 99);
+        assert!(mines.iter().all(|(x, y)| *x < 16 && *y < 30));
+    }
+
+    #[test]
+    fn neighbours_stay_on_board() {
+        let board = board(3, &[]);
+        assert_eq!(board.neighbours(0, 0).count(), 3);
+        assert_eq!(board.neighbours(0, 1).count(), 5);
+        assert_eq!(board.neighbours(1, 1).count(), 8);
+    }
+
+    #[test]
+    fn hex_board_counts_six_neighbours() {
+        // Odd rows are shifted right, so (1, 1)
+        // doesn't touch the mine any more
+        //  * 1 0 0
+        //   1 0 0 0
+        //  0 0 0 0
+        //   0 0 0 0
+        let mut board = board(4, &[(0, 0)]);
+        assert_eq!(board.tile(1, 1).mines_around, 1);
+        board.set_topology(Topology::HEX);
+        assert_eq!(board.topology(), Topology::HEX);
+        assert_eq!(board.neighbours(1, 1).count(), 6);
+        assert_eq!(board.tile(1, 1).mines_around, 0);
+        assert_eq!(board.tile(1, 0).mines_around, 1);
+        assert_eq!(board.tile(0, 1).mines_around, 1);
+
+        let Reveal::Opened(opened) = board.reveal(3, 3) else {
+            panic!("expected cells to open");
+        };
+        assert_eq!(opened.len(), 15);
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn wrap_counts_and_opens_over_edges() {
+        // Mine in the corner touches
+        // all the other corners
+        let mut board = board(4, &[(0, 0)]);
+        board.set_topology(Topology { wrap: true, ..Topology::SQUARE });
+        for (x, y) in [(0, 3), (3, 0), (3, 3), (1, 1)] {
+            assert_eq!(board.tile(x, y).mines_around, 1);
+        }
+        assert_eq!(board.tile(2, 2).mines_around, 0);
+
+        // Flood fill from the middle goes around,
+        // only numbers next to the mine stop it
+        let Reveal::Opened(opened) = board.reveal(2, 2) else {
+            panic!("expected cells to open");
+        };
+        assert_eq!(opened.len(), 15);
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn cross_counts_and_opens_only_in_line() {
+        // Mine touches only cells next to it
+        // in the same row or column
+        //  * 1 0 0
+        //  1 0 0 0
+        //  0 0 0 0
+        //  0 0 0 0
+        let mut board = board(4, &[(0, 0)]);
+        board.set_topology(Topology { neighbourhood: Neighbourhood::CROSS, ..Topology::SQUARE });
+        assert_eq!(board.tile(1, 1).mines_around, 0);
+        assert_eq!(board.tile(0, 1).mines_around, 1);
+
+        let Reveal::Opened(opened) = board.reveal(3, 3) else {
+            panic!("expected cells to open");
+        };
+        assert_eq!(opened.len(), 15);
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn multi_mine_cells_count_every_mine() {
+        let mines = [((0, 0), 3), ((2, 2), 2)].into_iter().collect();
+        let mut board = Board::with_mines(3, 3, 3, &mines);
+        assert_eq!(board.mines_amount(), 5);
+        assert_eq!(board.flags(), 5);
+        assert_eq!(board.tile(1, 1).mines_around, 5);
+        assert_eq!(board.tile(0, 1).mines_around, 3);
+
+        // Right clicks add flags until cell is full,
+        // next one takes them all away
+        for flags in [1, 2, 3] {
+            assert_eq!(board.toggle_flag(0, 0), Some(true));
+            assert_eq!(board.tile(0, 0).flags, flags);
+        }
+        assert_eq!(board.flags(), 2);
+        assert_eq!(board.toggle_flag(0, 0), Some(false));
+        assert_eq!(board.flags(), 5);
+
+        // Number is chorded when flags around
+        // add up to it, not when there are enough cells
+        for _ in 0..3 {
+            board.toggle_flag(0, 0);
+        }
+        board.reveal(0, 1);
+        assert!(matches!(board.chord(0, 1), Reveal::Opened(_)));
+
+        // Cells with mines are left closed
+        // no matter how many mines they have
+        for (x, y) in board.positions().collect::<Vec<_>>() {
+            if !board.tile(x, y).is_mine() {
+                board.reveal(x, y);
+            }
+        }
+        assert_eq!(board.status(), Status::Won);
+        assert_eq!(board.tile(2, 2).flags, 2);
+        assert_eq!(board.flags(), 0);
+    }
+
+    #[test]
+    fn lost_multi_mine_board_keeps_wrong_flag_counts() {
+        let mines = [((0, 0), 3), ((2, 2), 1), ((0, 2), 2), ((2, 0), 1)].into_iter().collect();
+        let mut board = Board::with_mines(3, 3, 3, &mines);
+        board.toggle_flag(0, 0);
+        board.toggle_flag(2, 2);
+        board.toggle_flag(2, 2);
+        for _ in 0..2 {
+            board.toggle_flag(0, 2);
+        }
+        board.toggle_flag(1, 1);
+        board.reveal(2, 0);
+        assert_eq!(board.status(), Status::Lost);
+
+        // 3 mines under 1 flag and 1 mine under 2 flags
+        // are both wrong and still have real amount of mines
+        for (x, y, mines) in [(0, 0, 3), (2, 2, 1)] {
+            assert!(board.tile(x, y).has_wrong_flags());
+            assert_eq!(board.tile(x, y).mines, mines);
+        }
+        assert!(board.tile(1, 1).has_wrong_flags());
+        assert!(!board.tile(0, 2).has_wrong_flags());
+    }
+
+    #[test]
+    fn lives_let_player_survive_mines() {
+        //  * 1 0
+        //  1 1 0
+        //  0 0 0
+        let mut board = board(3, &[(0, 0)]);
+        board.set_lives(2);
+        assert_eq!(board.lives_left(), 2);
+
+        assert_eq!(board.reveal(0, 0), Reveal::Survived { exploded: vec![(0, 0)], opened: vec![] });
+        assert_eq!(board.status(), Status::Playing);
+        assert_eq!(board.lives_left(), 1);
+        assert!(board.tile(0, 0).is_exploded());
+
+        // Exploded mine counts as flagged one
+        assert_eq!(board.flags(), 0);
+        board.reveal(0, 1);
+        assert!(matches!(board.chord(0, 1), Reveal::Opened(_)));
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn last_life_ends_the_game() {
+        let mut board = board(3, &[(0, 0), (2, 2)]);
+        board.set_lives(2);
+        board.reveal(2, 2);
+        assert_eq!(board.reveal(0, 0), Reveal::Exploded((0, 0)));
+        assert_eq!(board.status(), Status::Lost);
+        assert_eq!(board.lives_left(), 0);
+    }
+
+    #[test]
+    fn random_mines_stack_up_to_max() {
+        for seed in 0..20 {
+            let mut board = Board::pending(9, 9, 30, seed, FirstClick::Safe);
+            board.set_max_mines(3);
+            board.reveal(4, 4);
+
+            let mines: usize = board.positions().map(|(x, y)| board.tile(x, y).mines as usize).sum();
+            assert_eq!(mines, 30);
+            assert!(board.positions().all(|(x, y)| board.tile(x, y).mines <= 3));
+            assert!(!board.tile(4, 4).is_mine());
+        }
+    }
+
+    #[test]
+    fn fresh_board_keeps_layout_and_rules() {
+        let mut board = board(4, &[(0, 0), (3, 3)]);
+        board.set_topology(Topology { wrap: true, neighbourhood: Neighbourhood::KNIGHT, ..Topology::SQUARE });
+        board.set_lives(2);
+        board.reveal(1, 1);
+        board.toggle_flag(0, 0);
+
+        let fresh = board.fresh();
+        assert_eq!(fresh.topology(), board.topology());
+        assert_eq!(fresh.lives(), 2);
+        assert_eq!(fresh.flags(), 2);
+        assert!(fresh.is_started());
+        for (x, y) in board.positions() {
+            let (tile, fresh) = (board.tile(x, y), fresh.tile(x, y));
+            assert_eq!((fresh.mines, fresh.mines_around), (tile.mines, tile.mines_around));
+            assert!(!fresh.is_opened && !fresh.is_flagged());
+        }
+    }
+
+    #[test]
+    fn hex_opening_keeps_neighbours_free() {
+        for seed in 0..20 {
+            let mut board = Board::pending(9, 9, 30, seed, FirstClick::Opening);
+            board.set_topology(Topology::HEX);
+            board.reveal(3, 3);
+            assert_eq!(board.tile(3, 3).mines_around, 0);
+            assert!(board.neighbours(3, 3).all(|(x, y)| board.tile(x, y).is_opened));
+        }
+    }
+
+    fn mines(board: &Board) -> Vec<(usize, usize)> {
+        board.positions()
+            .filter(|(x, y)| board.tile(*x, *y).is_mine())
+            .collect()
+    }
+
+    #[test]
+    fn mines_are_placed_on_first_reveal() {
+        let mut board = Board::pending(10, 10, 25, 7, FirstClick::Safe);
+        assert!(mines(&board).is_empty());
+        assert_eq!(board.flags(), 25);
+        assert_eq!(board.first_reveal(), None);
+
+        assert!(matches!(board.reveal(4, 4), Reveal::Opened(_)));
+        assert_eq!(mines(&board).len(), 25);
+        assert!(!board.tile(4, 4).is_mine());
+        assert_eq!(board.first_reveal(), Some((4, 4)));
+    }
+
+    #[test]
+    fn first_click_is_always_safe() {
+        // Only one cell is free so the clicked one has to be it
+        for seed in 0..20 {
+            let mut board = Board::pending(4, 4, 15, seed, FirstClick::Safe);
+            assert_eq!(board.reveal(2, 1), Reveal::Opened(vec![(2, 1)]));
+            assert_eq!(board.status(), Status::Won);
+        }
+    }
+
+    #[test]
+    fn opening_first_click_is_zero() {
+        for seed in 0..20 {
+            let mut board = Board::pending(9, 9, 30, seed, FirstClick::Opening);
+            board.reveal(0, 0);
+            assert_eq!(board.tile(0, 0).mines_around, 0);
+            assert!(board.neighbours(0, 0).all(|(x, y)| board.tile(x, y).is_opened));
+        }
+    }
+
+    #[test]
+    fn opening_falls_back_when_board_is_too_dense() {
+        let mut board = Board::pending(3, 3, 8, 1, FirstClick::Opening);
+        assert_eq!(board.reveal(1, 1), Reveal::Opened(vec![(1, 1)]));
+        assert_eq!(mines(&board).len(), 8);
+    }
+
+    #[test]
+    fn flags_before_first_click_are_kept() {
+        let mut board = Board::pending(5, 5, 5, 3, FirstClick::Safe);
+        board.toggle_flag(0, 0);
+        board.toggle_flag(4, 4);
+        board.reveal(2, 2);
+        assert!(board.tile(0, 0).is_flagged());
+        assert_eq!(board.flags(), 3);
+    }
+
+    #[test]
+    fn no_guess_board_is_solvable() {
+        let no_guess = NoGuess { attempts: 1000 };
+        for seed in 0..5 {
+            let mut board = Board::pending(10, 10, 15, seed, FirstClick::Safe);
+            board.set_no_guess(Some(no_guess));
+            board.reveal(5, 5);
+            assert_eq!(board.tile(5, 5).mines_around, 0);
+
+            let board = Board::new(10, 10, &mines(&board).into_iter().collect());
+            assert!(solver::is_solvable(&board, 5, 5));
+        }
+    }
+
+    #[test]
+    fn no_guess_gives_up_after_attempts() {
+        // 50/50 is unavoidable here, generator
+        // has to stop and keep what it has
+        let mut board = Board::pending(2, 2, 1, 0, FirstClick::Safe);
+        board.set_no_guess(Some(NoGuess { attempts: 3 }));
+        board.reveal(0, 0);
+        assert_eq!(mines(&board).len(), 1);
+    }
+
+    #[test]
+    fn no_guess_gives_same_board_when_giving_up() {
+        // Dense board runs out of attempts, board it keeps
+        // has to depend on the seed only, not on the clock
+        let mines = || {
+            let mut board = Board::pending(8, 8, 30, 7, FirstClick::Safe);
+            board.set_no_guess(Some(NoGuess { attempts: 20 }));
+            board.reveal(4, 4);
+            assert!(!solver::is_solvable(&board.fresh(), 4, 4));
+            mines(&board)
+        };
+        assert_eq!(mines(), mines());
+    }
+
+    #[test]
+    fn same_seed_gives_same_board() {
+        let mines = |seed| {
+            let mut board = Board::pending(16, 16, 40, seed, FirstClick::Safe);
+            board.reveal(8, 8);
+            mines(&board)
+        };
+        assert_eq!(mines(12345), mines(12345));
+        assert_ne!(mines(12345), mines(12346));
+    }
+
+    #[test]
+    fn number_opens_single_cell() {
+        let mut board = board(3, &[(0, 0)]);
+        assert_eq!(board.reveal(1, 1), Reveal::Opened(vec![(1, 1)]));
+        assert!(!board.tile(0, 1).is_opened);
+        assert_eq!(board.reveal(1, 1), Reveal::Ignored);
+    }
+
+    #[test]
+    fn flood_fill_opens_zero_region() {
+        let mut board = board(4, &[(0, 0)]);
+        let Reveal::Opened(opened) = board.reveal(3, 3) else {
+            panic!("expected cells to open");
+        };
+        assert_eq!(opened.len(), 15);
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn flood_fill_stops_at_numbers_and_flags() {
+        // Mines split the board in two halves
+        let mut board = board(5, &[(0, 2), (1, 2), (2, 2), (3, 2), (4, 2)]);
+        board.toggle_flag(0, 0);
+        let Reveal::Opened(opened) = board.reveal(4, 0) else {
+            panic!("expected cells to open");
+        };
+        assert_eq!(opened.len(), 9);
+        assert!(opened.iter().all(|(_, y)| *y < 2));
+        assert!(!board.tile(0, 0).is_opened);
+        assert_eq!(board.status(), Status::Playing);
+    }
+
+    #[test]
+    fn clicking_mine_loses() {
+        let mut board = board(3, &[(1, 1)]);
+        assert_eq!(board.reveal(1, 1), Reveal::Exploded((1, 1)));
+        assert_eq!(board.status(), Status::Lost);
+        assert_eq!(board.reveal(0, 0), Reveal::Ignored);
+        assert_eq!(board.toggle_flag(0, 0), None);
+    }
+
+    #[test]
+    fn chord_opens_around_satisfied_number() {
+        //  * 1 0
+        //  1 1 0
+        //  0 0 0
+        let mut board = board(3, &[(0, 0)]);
+        board.reveal(0, 1);
+        assert_eq!(board.chord(0, 1), Reveal::Ignored);
+
+        board.toggle_flag(0, 0);
+        let Reveal::Opened(opened) = board.chord(0, 1) else {
+            panic!("expected cells to open");
+        };
+        assert!(opened.contains(&(1, 1)));
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn chord_with_wrong_flag_explodes() {
+        let mut board = board(3, &[(0, 0)]);
+        board.reveal(0, 1);
+        board.toggle_flag(1, 1);
+        assert_eq!(board.chord(0, 1), Reveal::Exploded((0, 0)));
+        assert_eq!(board.status(), Status::Lost);
+    }
+
+    #[test]
+    fn chord_ignores_closed_and_empty_cells() {
+        let mut board = board(3, &[(0, 0), (0, 2)]);
+        assert_eq!(board.chord(1, 1), Reveal::Ignored);
+        board.reveal(2, 2);
+        assert_eq!(board.status(), Status::Playing);
+        assert_eq!(board.chord(2, 2), Reveal::Ignored);
+    }
+
+    #[test]
+    fn flagged_cell_is_not_revealed() {
+        let mut board = board(3, &[(1, 1)]);
+        assert_eq!(board.toggle_flag(1, 1), Some(true));
+        assert_eq!(board.flags(), 0);
+        assert_eq!(board.reveal(1, 1), Reveal::Ignored);
+        assert_eq!(board.toggle_flag(1, 1), Some(false));
+        assert_eq!(board.flags(), 1);
+    }
+
+    #[test]
+    fn question_mark_replaces_flag_and_opens() {
+        let mut board = board(3, &[(0, 0)]);
+        board.toggle_flag(2, 2);
+        assert_eq!(board.toggle_question(2, 2), Some(true));
+        assert!(!board.tile(2, 2).is_flagged());
+        assert_eq!(board.flags(), 1);
+
+        // Question mark doesn't stop flood fill
+        board.reveal(2, 1);
+        assert!(board.tile(2, 2).is_opened);
+        assert!(!board.tile(2, 2).is_questioned);
+        assert_eq!(board.toggle_question(2, 2), None);
+    }
+
+    #[test]
+    fn opened_cell_cant_be_flagged() {
+        // Second mine keeps game going,
+        // won game has every mine flagged
+        let mut board = board(3, &[(0, 0), (0, 2)]);
+        board.reveal(2, 2);
+        assert_eq!(board.status(), Status::Playing);
+        assert_eq!(board.toggle_flag(2, 2), None);
+        assert_eq!(board.flags(), 2);
+    }
+
+    #[test]
+    fn win_after_all_safe_cells_opened() {
+        let mut board = board(2, &[(0, 0)]);
+        board.reveal(0, 1);
+        board.reveal(1, 0);
+        assert_eq!(board.status(), Status::Playing);
+        board.reveal(1, 1);
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn win_flags_every_mine() {
+        let mut board = board(3, &[(0, 0), (2, 2)]);
+        board.toggle_question(0, 0);
+        for (x, y) in [(0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (2, 0), (2, 1)] {
+            board.reveal(x, y);
+        }
+        assert_eq!(board.status(), Status::Won);
+        assert!(board.tile(0, 0).is_flagged() && !board.tile(0, 0).is_questioned);
+        assert!(board.tile(2, 2).is_flagged());
+        assert_eq!(board.flags(), 0);
+    }
+}
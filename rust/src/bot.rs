@@ -0,0 +1,132 @@
+use std::fmt;
+
+use crate::{board::{Board, FirstClick, Status}, solver};
+
+// Result of many games on the same board settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Report {
+    pub games: u32,
+    pub won: u32,
+    // Moves made without proof, first click is not counted
+    pub guesses: u32,
+}
+
+impl Report {
+    pub fn win_rate(&self) -> f64 {
+        match self.games {
+            0 => 0.0,
+            games => self.won as f64 / games as f64,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} won ({:.1}%), {:.2} guesses per game",
+            self.won,
+            self.games,
+            self.win_rate() * 100.0,
+            self.guesses as f64 / self.games.max(1) as f64,
+        )
+    }
+}
+
+// Plays board to the end the same way player would, through
+// reveal and toggle_flag. Deductions are used while there are any,
+// then the cell with the lowest chance of mine is opened.
+// Returns amount of guesses made
+pub fn play(board: &mut Board) -> u32 {
+    let mut guesses = 0;
+    board.reveal(board.height() / 2, board.width() / 2);
+
+    while board.status() == Status::Playing {
+        if let Some(deduction) = solver::deduce(board) {
+            for (x, y) in deduction.mines {
+                board.toggle_flag(x, y);
+            }
+            for (x, y) in deduction.safe {
+                board.reveal(x, y);
+            }
+            continue;
+        }
+
+        guesses += 1;
+        let guess = solver::probabilities(board)
+            .and_then(|chances| {
+                chances.into_iter()
+                    .min_by(|(a, a_chance), (b, b_chance)| a_chance.total_cmp(b_chance).then(a.cmp(b)))
+            })
+            .map(|(position, _)| position)
+            // Too many layouts to count, any closed cell will do
+            .or_else(|| board.positions().find(|(x, y)| {
+                let tile = board.tile(*x, *y);
+                !tile.is_opened && !tile.is_flagged()
+            }));
+
+        match guess {
+            Some((x, y)) => board.reveal(x, y),
+            None => break,
+        };
+    }
+
+    guesses
+}
+
+// Plays games with seeds from 0 to games
+pub fn run(width: usize, height: usize, mines_amount: usize, first_click: FirstClick, games: u32) -> Report {
+    let mut report = Report::default();
+    for seed in 0..games as u64 {
+        let mut board = Board::pending(width, height, mines_amount, seed, first_click);
+        report.guesses += play(&mut board);
+        report.games += 1;
+        if board.status() == Status::Won {
+            report.won += 1;
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{grid::difficulty_size, topology::Topology};
+
+    use super::*;
+
+    #[test]
+    fn plays_games_to_the_end() {
+        let report = run(9, 9, 10, FirstClick::Opening, 20);
+        assert_eq!(report.games, 20);
+        // Beginner board is won most of the time
+        assert!(report.won >= 10, "{report}");
+
+        let mut board = Board::pending(9, 9, 10, 1, FirstClick::Safe);
+        play(&mut board);
+        assert_ne!(board.status(), Status::Playing);
+
+        // Solver only knows neighbours, so hex works the same
+        let mut board = Board::pending(9, 9, 10, 2, FirstClick::Opening);
+        board.set_topology(Topology::HEX);
+        play(&mut board);
+        assert_ne!(board.status(), Status::Playing);
+    }
+
+    // Win rate of every difficulty, for tuning difficulty_size.
+    // Slow in debug, run with
+    // cargo test --release bot_report -- --ignored --nocapture
+    // BOT_GAMES sets amount of games per difficulty
+    #[test]
+    #[ignore]
+    fn bot_report() {
+        let games = std::env::var("BOT_GAMES").ok().and_then(|games| games.parse().ok()).unwrap_or(200);
+        for (difficulty, name) in ["Easy", "Medium", "Hard"].into_iter().enumerate() {
+            let (size, mines_amount) = difficulty_size(difficulty as f64);
+            let (size, mines_amount) = (size as usize, mines_amount as usize);
+            for first_click in [FirstClick::Safe, FirstClick::Opening] {
+                let report = run(size, size, mines_amount, first_click, games);
+                println!("{name} {size}x{size} {mines_amount} mines, {first_click:?}: {report}");
+            }
+        }
+    }
+}
@@ -1,26 +1,82 @@
-use godot::{classes::{Button, IButton}, obj::WithBaseField, prelude::*};
+use godot::{classes::{base_button::DrawMode, Button, IButton, InputEvent, InputEventMouseButton, StyleBoxEmpty, StyleBoxFlat}, global::MouseButtonMask, obj::{EngineBitfield, NewGd, WithBaseField}, prelude::*};
+
+use crate::board::{Status, Tile};
+
+// Everything cell can look like. CellGrid sets it
+// from Board and cell renders itself from it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CellState {
+    #[default]
+    Hidden,
+    // Amount of flags, more than 1 only on multi-mine boards
+    Flagged(u8),
+    Questioned,
+    // Amount of mines around, 0 is empty cell
+    Revealed(u8),
+    // Mine player opened
+    Exploded,
+    // Mines without flag and flags on safe
+    // cells are shown after game is lost.
+    // Mine has amount of mines in the cell
+    Mine(u8),
+    WrongFlag,
+    // Flags on multi-mine cell that don't add up
+    // to its mines, has real amount of them
+    WrongCount(u8),
+}
+
+impl CellState {
+    pub fn of(tile: &Tile, status: Status) -> Self {
+        match *tile {
+            Tile { is_opened: true, mines: 1.., .. } => CellState::Exploded,
+            Tile { is_opened: true, mines_around, .. } => CellState::Revealed(mines_around),
+            Tile { mines: 0, .. } if tile.has_wrong_flags() && status == Status::Lost => CellState::WrongFlag,
+            Tile { mines, .. } if tile.has_wrong_flags() && status == Status::Lost => CellState::WrongCount(mines),
+            Tile { flags: flags @ 1.., .. } => CellState::Flagged(flags),
+            Tile { mines: mines @ 1.., .. } if status == Status::Lost => CellState::Mine(mines),
+            Tile { is_questioned: true, .. } => CellState::Questioned,
+            _ => CellState::Hidden,
+        }
+    }
+
+    fn text(self) -> String {
+        match self {
+            CellState::Hidden => "·".to_string(),
+            CellState::Flagged(1) => "🚩".to_string(),
+            CellState::Flagged(amount) => format!("🚩{amount}"),
+            CellState::Questioned => "?".to_string(),
+            CellState::Revealed(0) => String::new(),
+            CellState::Revealed(amount) => amount.to_string(),
+            CellState::Exploded => "💥".to_string(),
+            CellState::Mine(1) => "💣".to_string(),
+            CellState::Mine(amount) => format!("💣{amount}"),
+            CellState::WrongFlag => "❌".to_string(),
+            CellState::WrongCount(amount) => format!("❌💣{amount}"),
+        }
+    }
+}
 
 #[derive(GodotClass)]
 #[class(base=Button)]
 pub struct Cell {
-    pub mines_around: i32,
-    pub is_mine: bool,
-    pub is_flagged: bool,
-
     // Idk if it's possible to not use
     // this field to get position, but for now
     // i have to do this in order to get
     // position of clicked cell
     pub position: (usize, usize),
 
+    state: CellState,
+
+    // Hex cells draw themselves as hexagon instead
+    // of button style and only take clicks inside it
+    hexagon: bool,
+    cursor: bool,
+
     base: Base<Button>,
 }
 
 #[godot_api]
 impl Cell {
-    #[signal]
-    fn click_on_bomb();
-
     #[signal]
     // This one accept index of object
     // works like id ig
@@ -28,64 +84,118 @@ impl Cell {
     fn open_cells(index: Variant);
 
     #[signal]
-    fn add_flag();
-    
+    fn toggle_flag(index: Variant);
+
     #[signal]
-    fn sub_flag();
+    fn chord_cells(index: Variant);
 
     #[func]
     // "Router" to handle right and left mouse buttons
     fn on_cell_click(&mut self) {
         let input = Input::singleton();
 
-        // For this if statement this code duplication is
-        // necessary. If i bring check for dot on top it will
-        // eliminate flag as well
-        if input.is_action_pressed("left_click") {
-            if self.base().get_text() != "·".into() {
+        let is_closed = matches!(self.state, CellState::Hidden | CellState::Questioned);
+        if input.is_action_pressed("chord") {
+            // Only opened numbers can be chorded
+            if !matches!(self.state, CellState::Revealed(1..)) {
+                return;
+            }
+            self.chord();
+        } else if input.is_action_pressed("left_click") {
+            // CellGrid decides if question mark can be opened
+            if !is_closed {
                 return;
             }
             self.left_click();
         } else if input.is_action_pressed("right_click") {
-            if self.base().get_text() != "·".into() && self.base().get_text() != "🚩".into() {
+            if !is_closed && !matches!(self.state, CellState::Flagged(_)) {
                 return;
             }
             self.right_click();
         }
     }
 
-    fn left_click(&mut self) {
-        // get_path() gets path from the root node to object
-        // that called this method
-        let path = self.base().get_path();
-        let mut cell = self.base_mut().get_node_as::<Cell>(&path);
-
-        if self.is_mine {
-            cell.set_text("💣");
-            self.base_mut().emit_signal("click_on_bomb", &[]);
-        } else {
-            let index = self.base().get_index();
-
-            // We can pass arguments with signals but it needs
-            // to be variant. Idk how to pass arrays or multiple elements,
-            // because i didn't need them here
-            self.base_mut().emit_signal("open_cells", &[index.to_variant()]);
+    pub fn set_state(&mut self, state: CellState) {
+        self.state = state;
+        self.base_mut().set_text(&state.text());
+    }
+
+    // Keyboard cursor is a thick border drawn instead
+    // of button style. Tint from probabilities and hint
+    // still shows through it
+    pub fn set_cursor(&mut self, cursor: bool) {
+        self.cursor = cursor;
+        if self.hexagon {
+            self.base_mut().queue_redraw();
+            return;
+        }
+
+        let mut style = StyleBoxFlat::new_gd();
+        style.set_bg_color(Color::from_rgba(0.25, 0.25, 0.25, 1.0));
+        style.set_border_color(Color::from_rgb(1.0, 0.85, 0.0));
+        style.set_border_width_all(3);
+
+        for name in ["normal", "hover", "pressed", "disabled"] {
+            if cursor {
+                self.base_mut().add_theme_stylebox_override(name, &style);
+            } else {
+                self.base_mut().remove_theme_stylebox_override(name);
+            }
         }
     }
 
-    fn right_click(&mut self) { 
-        // Reversing state of flagged
-        // and calling signals to change
-        // flag field in CellGrid
-        if self.is_flagged {
-            self.is_flagged = !self.is_flagged;
-            self.base_mut().set_text("·");
-            self.base_mut().emit_signal("add_flag", &[]);
-        } else {
-            self.is_flagged = true;
-            self.base_mut().set_text("🚩");
-            self.base_mut().emit_signal("sub_flag", &[]);
+    // Button styles are rectangles, so hex cell
+    // hides them and draws hexagon in draw
+    pub fn set_hexagon(&mut self, hexagon: bool) {
+        self.hexagon = hexagon;
+        for name in ["normal", "hover", "pressed", "disabled", "focus"] {
+            if hexagon {
+                self.base_mut().add_theme_stylebox_override(name, &StyleBoxEmpty::new_gd());
+            } else {
+                self.base_mut().remove_theme_stylebox_override(name);
+            }
         }
+        self.base_mut().queue_redraw();
+    }
+
+    // Pointy top hexagon touching every side of the cell
+    fn hexagon_points(&self) -> PackedVector2Array {
+        let Vector2 { x: width, y: height } = self.base().get_size();
+        PackedVector2Array::from(&[
+            Vector2::new(width / 2.0, 0.0),
+            Vector2::new(width, height / 4.0),
+            Vector2::new(width, height * 3.0 / 4.0),
+            Vector2::new(width / 2.0, height),
+            Vector2::new(0.0, height * 3.0 / 4.0),
+            Vector2::new(0.0, height / 4.0),
+        ])
+    }
+
+    fn left_click(&mut self) {
+        // Cell doesn't know if it is a mine.
+        // Board in CellGrid decides what happens
+        let index = self.base().get_index();
+
+        // We can pass arguments with signals but it needs
+        // to be variant. Idk how to pass arrays or multiple elements,
+        // because i didn't need them here
+        self.base_mut().emit_signal("open_cells", &[index.to_variant()]);
+    }
+
+    fn chord(&mut self) {
+        // Board checks if this is a number and
+        // if there are enough flags around it
+        let index = self.base().get_index();
+        self.base_mut().emit_signal("chord_cells", &[index.to_variant()]);
+    }
+
+    fn right_click(&mut self) { 
+        // Flag state is kept in Board as well,
+        // CellGrid will change text of this cell
+        // and flags counter. It also decides if
+        // flag turns into question mark
+        let index = self.base().get_index();
+        self.base_mut().emit_signal("toggle_flag", &[index.to_variant()]);
     }
 }
 
@@ -93,11 +203,116 @@ impl Cell {
 impl IButton for Cell {
     fn init(base: Base<Button>) -> Self {
         Self {
-            mines_around: 0,
             position: (0, 0),
-            is_mine: false,
-            is_flagged: false,
+            state: CellState::Hidden,
+            hexagon: false,
+            cursor: false,
             base
         }
     }
+
+    // Same colors default button style has
+    fn draw(&mut self) {
+        if !self.hexagon {
+            return;
+        }
+
+        let color = match self.base().get_draw_mode() {
+            DrawMode::HOVER | DrawMode::HOVER_PRESSED => Color::from_rgba(0.225, 0.225, 0.225, 0.6),
+            DrawMode::PRESSED => Color::from_rgba(0.0, 0.0, 0.0, 0.6),
+            DrawMode::DISABLED => Color::from_rgba(0.1, 0.1, 0.1, 0.3),
+            _ => Color::from_rgba(0.1, 0.1, 0.1, 0.6),
+        };
+        let points = self.hexagon_points();
+        self.base_mut().draw_colored_polygon(&points, color);
+
+        if self.cursor {
+            let mut outline = points.clone();
+            outline.push(points[0]);
+            self.base_mut()
+                .draw_polyline_ex(&outline, Color::from_rgb(1.0, 0.85, 0.0))
+                .width(3.0)
+                .done();
+        }
+    }
+
+    // Hexagons of next rows overlap corners of this
+    // cell, clicks there have to go to them
+    fn has_point(&self, point: Vector2) -> bool {
+        let size = self.base().get_size();
+        if !self.hexagon {
+            return Rect2::new(Vector2::ZERO, size).has_point(point);
+        }
+
+        let offset = (point - size / 2.0).abs();
+        offset.x <= size.x / 2.0 && offset.y <= size.y / 2.0 - offset.x * size.y / (2.0 * size.x)
+    }
+
+    // button_down signal is emitted only for the first
+    // pressed mouse button, so left and right pressed
+    // together have to be caught here
+    fn gui_input(&mut self, event: Gd<InputEvent>) {
+        let Ok(event) = event.try_cast::<InputEventMouseButton>() else {
+            return;
+        };
+        if !event.is_pressed() {
+            return;
+        }
+
+        let both = (MouseButtonMask::LEFT | MouseButtonMask::RIGHT).ord();
+        if event.get_button_mask().ord() & both == both {
+            self.chord();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(mines: u8, flags: u8) -> Tile {
+        Tile { mines, flags, ..Default::default() }
+    }
+
+    #[test]
+    fn opened_cells_show_what_is_under_them() {
+        let exploded = Tile { is_opened: true, mines_around: 2, ..tile(1, 0) };
+        let number = Tile { is_opened: true, mines_around: 2, ..tile(0, 0) };
+        for status in [Status::Playing, Status::Lost] {
+            // Mine wins over number around it
+            assert_eq!(CellState::of(&exploded, status), CellState::Exploded);
+            assert_eq!(CellState::of(&number, status), CellState::Revealed(2));
+        }
+        assert_eq!(CellState::of(&Tile { is_opened: true, ..tile(0, 0) }, Status::Won), CellState::Revealed(0));
+    }
+
+    #[test]
+    fn closed_cells_hide_mines_while_playing() {
+        let questioned = Tile { is_questioned: true, ..tile(2, 0) };
+        for status in [Status::Playing, Status::Won] {
+            assert_eq!(CellState::of(&tile(0, 0), status), CellState::Hidden);
+            assert_eq!(CellState::of(&tile(3, 0), status), CellState::Hidden);
+            assert_eq!(CellState::of(&questioned, status), CellState::Questioned);
+            // Wrong flags are not told before the game is lost
+            assert_eq!(CellState::of(&tile(0, 1), status), CellState::Flagged(1));
+            assert_eq!(CellState::of(&tile(3, 1), status), CellState::Flagged(1));
+            assert_eq!(CellState::of(&tile(1, 2), status), CellState::Flagged(2));
+        }
+    }
+
+    #[test]
+    fn lost_game_shows_mines_and_wrong_flags() {
+        let lost = |tile| CellState::of(&tile, Status::Lost);
+        assert_eq!(lost(tile(0, 0)), CellState::Hidden);
+        assert_eq!(lost(tile(1, 0)), CellState::Mine(1));
+        assert_eq!(lost(tile(3, 0)), CellState::Mine(3));
+        assert_eq!(lost(tile(2, 2)), CellState::Flagged(2));
+        assert_eq!(lost(tile(0, 1)), CellState::WrongFlag);
+        assert_eq!(lost(tile(3, 1)), CellState::WrongCount(3));
+        assert_eq!(lost(tile(1, 2)), CellState::WrongCount(1));
+
+        // Mine is shown instead of question mark over it
+        assert_eq!(lost(Tile { is_questioned: true, ..tile(1, 0) }), CellState::Mine(1));
+        assert_eq!(lost(Tile { is_questioned: true, ..tile(0, 0) }), CellState::Questioned);
+    }
 }
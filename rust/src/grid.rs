@@ -1,18 +1,51 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use godot::{classes::{ Button, GridContainer, IGridContainer}, obj::{NewGd, WithBaseField}, prelude::*};
-use rand::Rng;
+use godot::{classes::{control::{LayoutPreset, LayoutPresetMode, MouseFilter}, Button, Control, IControl, InputEvent, Time}, obj::{NewGd, WithBaseField}, prelude::*};
 
-use crate::cell::Cell;
+use crate::{board::{Board, FirstClick, NoGuess, Reveal, Status, MULTI_MINES}, cell::{Cell, CellState}, metrics::{self, Metrics}, replay::{Action, Move, Playback, Replay}, solver, topology::{Neighbourhood, Shape, Topology}};
 
 #[derive(GodotClass, Debug)]
-#[class(base=GridContainer)]
+#[class(base=Control)]
 pub struct CellGrid {
     cell_scene: Gd<PackedScene>,
-    grid_size: i32,
+    width: i32,
+    height: i32,
     cell_size: f32,
     mines_amount: u32,
-    cells_opened: u32,
+
+    // Same seed, size and mines_amount
+    // always give the same board. Random one
+    // is picked on clear_board if player didn't set it
+    seed: u64,
+
+    // What is guaranteed about first opened cell
+    first_click: FirstClick,
+    no_guess: bool,
+
+    // Most mines one cell can get, more
+    // than 1 for multi-mine game
+    max_mines: u8,
+
+    // Mines player can open before the game is
+    // lost, 1 for classic game
+    lives: u32,
+
+    // Shape of cells for new games. Continued
+    // games and replays take it from their board
+    topology: Topology,
+
+    // Right click goes flag -> question mark -> nothing
+    // if question_marks is on. Left click opens cell
+    // with question mark only if open_questions is on
+    question_marks: bool,
+    open_questions: bool,
+
+    // In practice mode mine doesn't end the game right away.
+    // Board before fatal click is kept in undo until
+    // player decides to take it back or give up
+    practice: bool,
+    undo: Option<Board>,
+    undos: u32,
 
     // This field is same as child nodes of CellGrid.
     // Elements in this vector can be changed.
@@ -20,24 +53,101 @@ pub struct CellGrid {
     // they are representing in godot
     cells: Vec<Vec<Gd<Cell>>>,
 
-    // This field is used to track which cells
-    // were opened and thus should be ignored if clicked
-    opened: Vec<(usize, usize)>,
+    // Faded copies of cells shown past wrapped
+    // edges, by position of the cell they copy
+    ghosts: HashMap<(usize, usize), Vec<Gd<Cell>>>,
+
+    // All game rules and state live here.
+    // CellGrid only renders what board says
+    board: Board,
+
+    // Moves of current game for replay and when
+    // game started in milliseconds. None if game
+    // is not recorded
+    moves: Option<Vec<Move>>,
+    started_at: u64,
+
+    // Replay being played on this grid right now
+    playback: Option<Playback>,
+
+    // Counted once mines are placed on first click.
+    // None before that
+    metrics: Option<Metrics>,
+    // Forced guesses play the whole board with solver, so
+    // they are counted only when asked for, from this cell
+    first_reveal: Option<(usize, usize)>,
+    forced_guesses: Option<u32>,
+
+    // Closed cells are tinted from green to red
+    // by chance of having mine under them
+    show_probabilities: bool,
+
+    // Cell keyboard keys act on. None until
+    // player presses any of them in a game
+    cursor: Option<(usize, usize)>,
+    cursor_repeat: Option<CursorRepeat>,
+
+    // Hud has focus and takes keys and
+    // gamepad buttons instead of the grid
+    menu_open: bool,
+
+    base: Base<Control>
+}
+
+// Biggest side of the grid in pixels. Cells get
+// smaller than cell_size if board doesn't fit in it
+const GRID_PIXELS: f32 = 700.0;
+
+// Gap between cells in pixels
+const CELL_SEPARATION: f32 = 1.0;
 
-    pub flags: i32,
+// Pointy top hexagon is this many times taller than
+// wide, 2 / sqrt(3). Its rows overlap by a quarter
+const HEX_HEIGHT: f32 = 1.154_700_5;
 
-    base: Base<GridContainer>
+// Hard board comes out solvable about once in 10 tries
+// and every try takes few milliseconds, so this is
+// plenty while still not freezing the game
+const NO_GUESS: NoGuess = NoGuess { attempts: 500 };
+
+// Held direction moves cursor again after delay,
+// every next step comes sooner so big boards
+// can be crossed quickly with stick or arrows
+#[derive(Debug, Clone, Copy)]
+struct CursorRepeat {
+    action: &'static str,
+    direction: (i32, i32),
+    wait: f64,
+    delay: f64,
 }
 
-// Directions are used as usize you have to add +1
-// for it to work. It's a little bit confusing,
-// but for me it's better than having overhead
-// converting usize to i32 and vise versa
-const DIRECTIONS: [(usize, usize); 8] = [
-    (2, 2), (2, 1), (2, 0), (1, 2),
-    (1, 0), (0, 2), (0, 1), (0, 0),
+const CURSOR_DIRECTIONS: [(&str, (i32, i32)); 4] = [
+    ("cursor_up", (-1, 0)),
+    ("cursor_down", (1, 0)),
+    ("cursor_left", (0, -1)),
+    ("cursor_right", (0, 1)),
 ];
 
+// Seconds before the first repeat, each next delay
+// is CURSOR_SPEEDUP of previous one down to CURSOR_FASTEST
+const CURSOR_DELAY: f64 = 0.35;
+const CURSOR_SPEEDUP: f64 = 0.8;
+const CURSOR_FASTEST: f64 = 0.04;
+
+// Grid uses difficulty multiplier to create its values
+// like grid_size and mines_amount
+// simplified formulas:
+// grid_size has base value of 10.
+// grid_size = grid_size + (5 * difficulty)
+// mines_amount has base valie of 10.
+// mines_amount = (grid_size * grid_size) * (0.1 + 0.05 * difficulty)
+// it adds 5% of mines on each difficuty
+pub fn difficulty_size(difficulty: f64) -> (i32, u32) {
+    let grid_size = 10 + (5.0 * difficulty) as i32;
+    let mines_amount = ((grid_size * grid_size) as f64 * (0.1 + (0.05 * difficulty))) as u32;
+    (grid_size, mines_amount)
+}
+
 #[godot_api]
 impl CellGrid {
     #[signal]
@@ -52,6 +162,17 @@ impl CellGrid {
     #[signal]
     fn change_score();
 
+    #[signal]
+    fn replay_finished();
+
+    #[signal]
+    fn mine_hit();
+
+    // Mine took a life in lives mode. Penalty
+    // is false in replays, they don't change score
+    #[signal]
+    fn life_lost(penalty: bool);
+
     #[func]
     fn disable_buttons(&mut self) {
         let children = self.base().get_children();
@@ -65,141 +186,177 @@ impl CellGrid {
         }
     }
 
+    #[func]
+    fn enable_buttons(&mut self) {
+        for cell in self.cells.iter_mut().flatten() {
+            cell.set_disabled(false);
+        }
+    }
+
     #[func]
     // difficulties: 0 - easy, 1 - medium, 2 - hard;
     pub fn init_grid(&mut self, difficulty: f64) {
-        // Grid uses difficulty multiplier to create its values
-        // like grid_size and mines_amount
-        // simplified formulas:
-        // grid_size has base value of 10.
-        // grid_size = grid_size + (5 * difficulty)
-        self.grid_size = self.grid_size + (5.0 * difficulty) as i32;
-        // mines_amount has base valie of 10.
-        // mines_amount = (grid_size * grid_size) * (0.1 + 0.05 * difficulty)
-        // it adds 5% of mines on each difficuty
-        self.mines_amount = ((self.grid_size * self.grid_size) as f64 * (0.1 + (0.05 * difficulty))) as u32;
+        let (grid_size, mines_amount) = difficulty_size(difficulty);
+        self.width = grid_size;
+        self.height = grid_size;
+        self.mines_amount = mines_amount;
     
         self.gen_grid();
     }
 
+    #[func]
+    // Custom game, e.g. classic expert is 30x16 with 99 mines.
+    // Values have to be checked with Board::check_size first
+    pub fn init_custom_grid(&mut self, width: i32, height: i32, mines_amount: i32) {
+        self.width = width;
+        self.height = height;
+        self.mines_amount = mines_amount as u32;
+
+        self.gen_grid();
+    }
+
     #[func]
     pub fn gen_grid(&mut self) {
-        let grid_size = self.grid_size;
-
-        // CellGrid is based on GridContainer godot class
-        self.base_mut().set_columns(grid_size);
-
-        // Amount of flags should be equal to mines themselves
-        self.flags = self.mines_amount as i32;
-
-        let mut mines = HashSet::new();
-        let mut rng = rand::thread_rng();
-
-        while mines.len() < self.mines_amount.try_into().unwrap() {
-            // Generating positions x and y for mines.
-            // It is a hashset so it guarantees no duplicates occur
-            let x = rng.gen_range(0..grid_size) as usize;
-            let y = rng.gen_range(0..grid_size) as usize;
-            mines.insert((x, y));
-
-            // godot_print!("Mines created: {}", set.len());
-        }
-        
-        // With capacity is used to remove all allocations beside first
-        // row vector does allocation every loop, but idk if
-        // i can eliminate creating it at all. I have to give ownership
-        // and move it to vector, so need to create new one every time
-        let mut matrix = Vec::with_capacity(grid_size as usize);
-
-        // Creating grid full of empty cells
-        for i in 0..grid_size {
-            let mut row = Vec::with_capacity(grid_size as usize);
-            for k in 0..grid_size {
-
-                // cell_scene is used like template for creating instances
-                // of what it represents. In our case Cell
-                let cell_scene = self.cell_scene.instantiate_as::<Button>();
-                let mut mine = cell_scene.cast::<Cell>();
-
-                let mut mine_b = mine.bind_mut();
-                mine_b.position = (i as usize, k as usize);
-
-                // without drop it complains about 2 mutable references
-                // so need to drop it manually
-                drop(mine_b);
-                row.push(mine);
-            }
-            matrix.push(row);
-        }
-
-        // Inserting mines in our empty grid
-        for (i, k) in mines.iter() {
-
-            // unwrapping is safe because mines were created based on grid_size
-            // as well as our Grid, so i and k 100% valid indexes
-            let mine = matrix.get_mut(*i).unwrap().get_mut(*k).unwrap();
-
-            let mut mine = mine.bind_mut();
-            mine.is_mine = true;
-
-            // without drop it complains about 2 mutable references
-            // so need to drop it manually
-            drop(mine);
-
-            // For every mine we increase mines_around field
-            // of cells around in square shape by 1
-            for (dx, dy) in DIRECTIONS.iter() {
-                
-                // Checking if it is valid matrix indexes
-                // if not, skip it
-                let nx = match (i + 1).checked_sub(*dx) {
-                    Some(nx) => {
-                        if nx >= self.grid_size as usize {
-                            continue;
-                        }
-                        nx
-                    },
-                    None => {
-                        continue
-                    },
-                };
-                let ny = match (k + 1).checked_sub(*dy) {
-                    Some(ny) => {
-                        if ny >= self.grid_size as usize {
-                            continue;
-                        }
-                        ny
-                    },
-                    None => {
-                        continue
-                    },
-                };
+        let (width, height) = (self.width, self.height);
 
-                // Safe to unwrap as we checked nx and ny
-                // And they are valid indexes of matrix
-                let mine = matrix.get_mut(nx).unwrap().get_mut(ny).unwrap();
-                let mut mine = mine.bind_mut();
-                if !mine.is_mine {
-                    mine.mines_around += 1;
-                }
-            }
+        // Mines are not placed yet. Board will do it
+        // on first click so player can't lose right away
+        self.board = Board::pending(width as usize, height as usize, self.mines_amount as usize, self.seed, self.first_click);
+        // Solver can't play multi-mine boards,
+        // so they are never checked for guessing
+        self.board.set_max_mines(self.max_mines);
+        self.board.set_lives(self.lives);
+        if self.no_guess && self.max_mines == 1 {
+            self.board.set_no_guess(Some(NO_GUESS));
+        }
+
+        // Hexagons always count their own 6 cells, so
+        // neighbourhood picked for squares doesn't matter
+        let mut topology = self.topology;
+        if topology.shape == Shape::Hex {
+            topology.neighbourhood = Neighbourhood::AROUND;
+        }
+        self.board.set_topology(topology);
+
+        // GameTimer is started right after this
+        // so replay time is counted from here
+        self.moves = Some(vec![]);
+        self.started_at = Time::singleton().get_ticks_msec();
+
+        self.add_cells();
+    }
 
+    // Continues saved game. Board keeps everything
+    // so only cells have to be created
+    pub fn load_board(&mut self, board: Board) {
+        self.width = board.width() as i32;
+        self.height = board.height() as i32;
+        self.mines_amount = board.mines_amount() as u32;
+        self.seed = board.seed();
+        self.board = board;
+
+        // Moves before save are lost, so
+        // continued game is not recorded
+        self.moves = None;
+
+        // First click is not saved either,
+        // any opened cell is as good to start from
+        self.first_reveal = self.board.positions().find(|(x, y)| {
+            let tile = self.board.tile(*x, *y);
+            tile.is_opened && !tile.is_exploded()
+        });
+        self.metrics = self.first_reveal.map(|_| metrics::measure(&self.board));
+        self.forced_guesses = None;
+
+        self.add_cells();
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // Cell look follows its tile, so this is
+    // called for every tile that changed
+    fn render(&mut self, x: usize, y: usize) {
+        let state = CellState::of(self.board.tile(x, y), self.board.status());
+        self.cells[x][y].bind_mut().set_state(state);
+        for ghost in self.ghosts.get_mut(&(x, y)).into_iter().flatten() {
+            ghost.bind_mut().set_state(state);
+        }
+    }
+
+    fn render_all(&mut self) {
+        for (x, y) in self.board.positions().collect::<Vec<_>>() {
+            self.render(x, y);
         }
+    }
+
+    // Creates Cell node for every tile of the board.
+    // Wrapped edges also get faded ghosts of the opposite
+    // edge next to them, so it's seen what they touch
+    fn add_cells(&mut self) {
+        let (width, height) = (self.width, self.height);
+        let topology = self.board.topology();
+        let hex = topology.shape == Shape::Hex;
+
+        // Ghosts take one more row or column on both sides
+        let ghost_rows = topology.wraps_rows(height as usize) as i32;
+        let ghost_columns = topology.wraps_columns() as i32;
+        let (all_rows, all_columns) = (height + 2 * ghost_rows, width + 2 * ghost_columns);
+
+        // Grid size measured in cell widths. Hex rows
+        // are shifted, so the grid is half a cell wider
+        let (columns, rows) = if hex {
+            (all_columns as f32 + 0.5, (all_rows as f32 * 0.75 + 0.25) * HEX_HEIGHT)
+        } else {
+            (all_columns as f32, all_rows as f32)
+        };
+
+        // Big custom boards won't fit on screen with
+        // default cell_size
+        let cell_width = self.cell_size.min(GRID_PIXELS / columns.max(rows) - CELL_SEPARATION);
+        let (cell_height, row_step) = if hex {
+            (cell_width * HEX_HEIGHT, cell_width * HEX_HEIGHT * 0.75)
+        } else {
+            (cell_width, cell_width)
+        };
+        let cell_size = Vector2::new(cell_width, cell_height);
+        let column_step = cell_width + CELL_SEPARATION;
+        let row_step = row_step + CELL_SEPARATION;
+
+        // CellGrid is plain Control and places cells itself,
+        // so it's resized to fit them and centered again
+        let size = Vector2::new(
+            columns * column_step - CELL_SEPARATION,
+            (all_rows - 1) as f32 * row_step + cell_height,
+        );
+        self.base_mut().set_custom_minimum_size(size);
+        self.base_mut()
+            .set_offsets_preset_ex(LayoutPreset::CENTER)
+            .resize_mode(LayoutPresetMode::MINSIZE)
+            .done();
+
+        // Top left corner of cell in row i and column k.
+        // Ghosts are in rows -1 and height, columns -1 and width.
+        // Odd hex rows are shifted half a cell right
+        let corner = |i: i32, k: i32| {
+            let shift = if hex && i.rem_euclid(2) == 1 { column_step / 2.0 } else { 0.0 };
+            Vector2::new(
+                (k + ghost_columns) as f32 * column_step + shift,
+                (i + ghost_rows) as f32 * row_step,
+            )
+        };
 
-        // Finally we have our Grid set up with
-        // mines and cells with number of mines
-        // around it.
         // Here we render a grid
-        for rows in matrix {
+        for i in 0..height {
             let mut struct_row = vec![];
-            for mut cell in rows {
-                cell.set_custom_minimum_size(Vector2::from_tuple((self.cell_size, self.cell_size)));
+            for k in 0..width {
+                let mut cell = self.new_cell((i as usize, k as usize), corner(i, k), cell_size);
 
                 // Connecting signal from every cell to our CellGrid
-                cell.connect("click_on_bomb", &self.base().callable("on_lose_game"));
                 cell.connect("open_cells", &self.base().callable("open_cells"));
-                cell.connect("add_flag", &self.base().callable("add_flag"));
-                cell.connect("sub_flag", &self.base().callable("sub_flag"));
+                cell.connect("toggle_flag", &self.base().callable("toggle_flag"));
+                cell.connect("chord_cells", &self.base().callable("chord_cells"));
 
                 // Adding cell as child node of CellGrid in Godot
                 self.base_mut().add_child(&cell);
@@ -212,106 +369,581 @@ impl CellGrid {
             // if needed
             self.cells.push(struct_row);
         }
+
+        for i in -ghost_rows..height + ghost_rows {
+            for k in -ghost_columns..width + ghost_columns {
+                if (0..height).contains(&i) && (0..width).contains(&k) {
+                    continue;
+                }
+
+                // Ghost only shows cell from the other
+                // side, clicks go through it
+                let source = (i.rem_euclid(height) as usize, k.rem_euclid(width) as usize);
+                let mut ghost = self.new_cell(source, corner(i, k), cell_size);
+                ghost.set_mouse_filter(MouseFilter::IGNORE);
+                ghost.set_disabled(true);
+                ghost.set_modulate(Color::from_rgba(1.0, 1.0, 1.0, 0.35));
+                self.base_mut().add_child(&ghost);
+                self.ghosts.entry(source).or_default().push(ghost);
+            }
+        }
+
+        // Continued game already has some cells opened
+        self.render_all();
+        self.update_probabilities();
+    }
+
+    // Cell for tile on position, placed at corner
+    // in pixels. Caller adds it to the tree
+    fn new_cell(&self, position: (usize, usize), corner: Vector2, size: Vector2) -> Gd<Cell> {
+        // cell_scene is used like template for creating instances
+        // of what it represents. In our case Cell
+        let cell_scene = self.cell_scene.instantiate_as::<Button>();
+        let mut cell = cell_scene.cast::<Cell>();
+        cell.bind_mut().position = position;
+
+        cell.set_custom_minimum_size(size);
+        cell.set_size(size);
+        cell.set_position(corner);
+        cell.bind_mut().set_hexagon(self.board.topology().shape == Shape::Hex);
+        cell
     }
 
     #[func]
-    fn open_cells(&mut self, index: Variant) {
+    // Triggered by probabilities checkbox in hud.
+    // Can be switched in the middle of the game
+    pub fn set_show_probabilities(&mut self, show: bool) {
+        self.show_probabilities = show;
+        self.update_probabilities();
+    }
+
+    // Highlights cell solver suggests and returns why.
+    // None if there is nothing to suggest, e.g. game
+    // is not started yet and first click is safe anyway
+    pub fn show_hint(&mut self) -> Option<String> {
+        if self.playback.is_some() || !self.board.is_started() {
+            return None;
+        }
+
+        let hint = solver::hint(&self.board)?;
+        let (x, y) = hint.position;
+        self.cells[x][y].set_self_modulate(Color::from_rgb(1.0, 0.85, 0.2));
+        Some(hint.text)
+    }
+
+    // Tints every closed cell by its chance of being a mine
+    // and shows the chance on hover. Called after every move,
+    // clears tint if probabilities are off or game is over.
+    // Hint highlight is cleared here as well
+    fn update_probabilities(&mut self) {
+        let chances = if self.show_probabilities {
+            solver::probabilities(&self.board)
+        } else {
+            None
+        };
+
+        let safe = Color::from_rgb(0.4, 1.0, 0.4);
+        let mine = Color::from_rgb(1.0, 0.3, 0.3);
+        for (x, y) in self.board.positions() {
+            let cell = &mut self.cells[x][y];
+            match chances.as_ref().and_then(|chances| chances.get(&(x, y))) {
+                Some(&chance) => {
+                    cell.set_self_modulate(safe.lerp(mine, chance));
+                    cell.set_tooltip_text(&format!("{:.0}%", chance * 100.0));
+                },
+                None => {
+                    cell.set_self_modulate(Color::WHITE);
+                    cell.set_tooltip_text("");
+                },
+            }
+        }
+    }
+
+    #[func]
+    // Has to be called after clear_board
+    // and before init_grid to take effect
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    #[func]
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[func]
+    // If true first click always opens
+    // empty area instead of single cell.
+    // Has to be called before init_grid as well
+    pub fn set_safe_opening(&mut self, safe_opening: bool) {
+        self.first_click = if safe_opening {
+            FirstClick::Opening
+        } else {
+            FirstClick::Safe
+        };
+    }
+
+    #[func]
+    // If true cells are hexagons with 6 neighbours
+    // instead of squares. Has to be called before init_grid as well
+    pub fn set_hex(&mut self, hex: bool) {
+        self.topology.shape = if hex {
+            Shape::Hex
+        } else {
+            Shape::Square
+        };
+    }
+
+    #[func]
+    // If true edges of the board are glued together,
+    // so cells on the edge have neighbours on the other side.
+    // Has to be called before init_grid as well
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.topology.wrap = wrap;
+    }
+
+    #[func]
+    // Which cells around are counted by numbers, id
+    // is the index in Neighbourhood::ALL. Unknown ids
+    // fall back to 3x3. Has to be called before init_grid as well
+    pub fn set_neighbourhood(&mut self, id: i32) {
+        self.topology.neighbourhood = usize::try_from(id)
+            .ok()
+            .and_then(|id| Neighbourhood::ALL.get(id).copied())
+            .unwrap_or_default();
+    }
+
+    #[func]
+    // If true board is generated so it can be
+    // solved without guessing from the first click.
+    // Has to be called before init_grid as well
+    pub fn set_no_guess(&mut self, no_guess: bool) {
+        self.no_guess = no_guess;
+    }
+
+    #[func]
+    // If true cells can hold up to 3 mines and take as
+    // many flags. Has to be called before init_grid as well
+    pub fn set_multi_mines(&mut self, multi_mines: bool) {
+        self.max_mines = if multi_mines { MULTI_MINES } else { 1 };
+    }
+
+    #[func]
+    // Every opened mine takes a life and game is lost
+    // with the last one. Has to be called before init_grid as well
+    pub fn set_lives(&mut self, lives: i32) {
+        self.lives = lives.max(1) as u32;
+    }
 
-        // Using option because compiler complains
-        // about clicked_cell could be uninitialized.
-        // But it's impossible because this function is called
+    #[func]
+    // Unlike other settings this one is only
+    // about input, so it works on continued games too
+    pub fn set_question_marks(&mut self, question_marks: bool, open_questions: bool) {
+        self.question_marks = question_marks;
+        self.open_questions = open_questions;
+    }
+
+    #[func]
+    // Has to be called before init_grid as well
+    pub fn set_practice(&mut self, practice: bool) {
+        self.practice = practice;
+    }
+
+    pub fn is_practice(&self) -> bool {
+        self.practice
+    }
+
+    // Board from before the click on mine
+    // practice game is waiting to undo
+    pub fn undo_board(&self) -> Option<&Board> {
+        self.undo.as_ref()
+    }
+
+    pub fn undos(&self) -> u32 {
+        self.undos
+    }
+
+    // Continued practice game keeps its undos
+    pub fn set_undos(&mut self, undos: u32) {
+        self.undos = undos;
+    }
+
+    #[func]
+    // Takes back click on mine in practice mode.
+    // Triggered by undo button in hud
+    pub fn undo_move(&mut self) {
+        let Some(board) = self.undo.take() else {
+            return;
+        };
+        self.board = board;
+        self.undos += 1;
+
+        // Replay goes on as if mine was never clicked
+        if let Some(moves) = self.moves.as_mut() {
+            moves.pop();
+        }
+
+        self.render_all();
+        self.enable_buttons();
+        self.update_probabilities();
+        self.base_mut().emit_signal("change_flags", &[]);
+    }
+
+    #[func]
+    // Ends practice game after click on mine.
+    // Triggered by give up button in hud
+    pub fn give_up(&mut self) {
+        if self.undo.take().is_some() {
+            self.on_lose_game();
+        }
+    }
+
+    // Finds position of the cell that sent signal.
+    // Cells are passing their index as Variant
+    fn position_of(&self, index: &Variant) -> Option<(usize, usize)> {
+        self.cells
+            .iter()
+            .flatten()
+            .find(|cell| cell.get_index().to_variant() == *index)
+            .map(|cell| cell.bind().position)
+    }
+
+    #[func]
+    fn open_cells(&mut self, index: Variant) {
+        // None is impossible because this function is called
         // on objects that are in vector in CellGrid struct
         // and thus are always valid
-        let mut clicked_cell = None;
-        'outer: for rows in self.cells.iter_mut() {
-            for item in rows.iter_mut() {
-                if item.get_index().to_variant() == index {
-                    clicked_cell = Some(item);
-                    break 'outer;
-                }
-            }
+        let Some(position) = self.position_of(&index) else {
+            return;
+        };
+        self.open_at(position);
+    }
+
+    // Mouse and keyboard both open cells through here
+    fn open_at(&mut self, position: (usize, usize)) {
+        let (x, y) = position;
+        // Question mark protects cell from
+        // misclick same as flag unless turned off
+        if self.board.tile(x, y).is_questioned && !self.open_questions {
+            return;
         }
+        self.apply(Action::Reveal, (x, y));
+    }
 
-        // So unwrap here is safe to use
-        // No panics will occur
-        let clicked_cell = clicked_cell.unwrap();
-        let position = clicked_cell.bind_mut().position;
+    #[func]
+    // Triggered when player chords on opened number.
+    // Board checks if flags around it match the number
+    fn chord_cells(&mut self, index: Variant) {
+        let Some(position) = self.position_of(&index) else {
+            return;
+        };
+        self.apply(Action::Chord, position);
+    }
 
-        let mut stack = vec![position];
+    #[func]
+    fn toggle_flag(&mut self, index: Variant) {
+        let Some(position) = self.position_of(&index) else {
+            return;
+        };
+        self.flag_at(position);
+    }
 
-        while let Some((x , y)) = stack.pop() {
-            if self.opened.contains(&(x, y)) {
-                continue;
-            }
-            self.opened.push((x, y));
-            
-            if self.cells[x][y].bind().mines_around == 0 {
-                self.cells[x][y].set_text("");  
-            } else {
-                let amount = self.cells[x][y].bind().mines_around;
-                self.cells[x][y].set_text(&amount.to_string());
-                self.base_mut().emit_signal("change_score", &[]);
-            }
-            
-            // Need to keep track of opened cells
-            // as this game win condition relies on:
-            // total cells - mines == cells_opened
-            self.cells_opened += 1;
-
-            // If cell is not empty cell then continue
-            // and don't add cells around it to stack
-            // Only 1 cell with mines_around != 0
-            // is needed to be open
-            if self.cells[x][y].bind().mines_around != 0 {
-                continue;
-            }
+    fn flag_at(&mut self, position: (usize, usize)) {
+        let (x, y) = position;
+        // Replay needs to know which one it was.
+        // Question mark left from game with them
+        // turned on is removed by right click as well.
+        // Multi-mine cells take flags until they are full
+        let tile = self.board.tile(x, y);
+        let is_full = tile.flags == self.board.max_mines();
+        let action = if tile.is_questioned {
+            Action::Unquestion
+        } else if is_full && self.question_marks {
+            Action::Question
+        } else if is_full {
+            Action::Unflag
+        } else {
+            Action::Flag
+        };
+        self.apply(action, (x, y));
+    }
 
-            // Adding cells around current one in 8 directions
-            for (dx, dy) in DIRECTIONS.iter() {
-                // Checking if directions point to valid
-                // index in matrix
-                let nx = match (x + 1).checked_sub(*dx) {
-                    Some(nx) => {
-                        if nx >= self.grid_size as usize {
-                            continue;
-                        }
-                        nx
-                    },
-                    None => continue,
+    // Keyboard works only while cells can be clicked,
+    // not in replay, after game over or when practice
+    // game waits for undo
+    fn accepts_keys(&self) -> bool {
+        !self.cells.is_empty()
+            && self.board.status() == Status::Playing
+            && self.playback.is_none()
+            && self.undo.is_none()
+            && !self.menu_open
+    }
+
+    #[func]
+    // Triggered by hud when start or escape
+    // moves focus between grid and hud
+    pub fn set_menu_open(&mut self, menu_open: bool) {
+        self.menu_open = menu_open;
+        self.cursor_repeat = None;
+    }
+
+    // Called every frame, moves cursor while
+    // direction that started repeat is held
+    fn repeat_cursor(&mut self, delta: f64) {
+        let Some(mut repeat) = self.cursor_repeat.take() else {
+            return;
+        };
+        if !self.accepts_keys() || !Input::singleton().is_action_pressed(repeat.action) {
+            return;
+        }
+
+        repeat.wait -= delta;
+        while repeat.wait <= 0.0 {
+            self.move_cursor(repeat.direction.0, repeat.direction.1);
+            repeat.delay = (repeat.delay * CURSOR_SPEEDUP).max(CURSOR_FASTEST);
+            repeat.wait += repeat.delay;
+        }
+        self.cursor_repeat = Some(repeat);
+    }
+
+    // Moves cursor by (rows, columns) and stops at the edges
+    // unless they wrap.
+    // First key press only shows cursor in the middle of the grid
+    fn move_cursor(&mut self, rows: i32, columns: i32) {
+        let (height, width) = (self.board.height() as i32, self.board.width() as i32);
+        let cursor = match self.cursor {
+            // Cursor goes over wrapped edge same as numbers do
+            Some((x, y)) => {
+                let topology = self.board.topology();
+                let step = |position: usize, by: i32, size: i32, wraps: bool| {
+                    let position = position as i32 + by;
+                    if wraps {
+                        position.rem_euclid(size) as usize
+                    } else {
+                        position.clamp(0, size - 1) as usize
+                    }
                 };
-                let ny = match (y + 1).checked_sub(*dy) {
-                    Some(ny) => {
-                        if ny >= self.grid_size as usize {
-                            continue;
-                        }
-                        ny
-                    },
-                    None => continue,
+                (
+                    step(x, rows, height, topology.wraps_rows(height as usize)),
+                    step(y, columns, width, topology.wraps_columns()),
+                )
+            },
+            None => ((height / 2) as usize, (width / 2) as usize),
+        };
+
+        if let Some((x, y)) = self.cursor {
+            self.cells[x][y].bind_mut().set_cursor(false);
+        }
+        self.cells[cursor.0][cursor.1].bind_mut().set_cursor(true);
+        self.cursor = Some(cursor);
+    }
+
+    // Every move of player and replay goes through here
+    fn apply(&mut self, action: Action, position: (usize, usize)) {
+        let (x, y) = position;
+        match action {
+            Action::Reveal | Action::Chord => {
+                let was_started = self.board.is_started();
+                let before = (self.practice && self.playback.is_none()).then(|| self.board.clone());
+                let reveal = if action == Action::Reveal {
+                    self.board.reveal(x, y)
+                } else {
+                    self.board.chord(x, y)
                 };
+                if !was_started && self.board.is_started() {
+                    self.metrics = Some(metrics::measure(&self.board));
+                    self.first_reveal = Some((x, y));
+                    self.forced_guesses = None;
+                }
+                if reveal != Reveal::Ignored {
+                    self.record(action, (x, y));
+                }
+                if matches!(reveal, Reveal::Exploded(_)) {
+                    self.undo = before;
+                }
+                self.show_reveal(reveal);
+            },
+            Action::Flag | Action::Unflag => {
+                // Replay can't flag something that is flagged already.
+                // Flag adds one more flag, unflag takes all of them
+                let is_full = self.board.tile(x, y).flags == self.board.max_mines();
+                if is_full != (action == Action::Unflag) {
+                    return;
+                }
+
+                // Reversing state of flagged
+                // and calling signal to change
+                // flags in hud
+                if self.board.toggle_flag(x, y).is_none() {
+                    return;
+                }
+                self.render(x, y);
+                self.record(action, (x, y));
+                self.base_mut().emit_signal("change_flags", &[]);
+            },
+            Action::Question | Action::Unquestion => {
+                if self.board.tile(x, y).is_questioned != (action == Action::Unquestion) {
+                    return;
+                }
+
+                // Flag replaced by question mark
+                // has to leave flags counter
+                let was_flagged = self.board.tile(x, y).is_flagged();
+                if self.board.toggle_question(x, y).is_none() {
+                    return;
+                }
+                self.render(x, y);
+                self.record(action, (x, y));
+                if was_flagged {
+                    self.base_mut().emit_signal("change_flags", &[]);
+                }
+            },
+        }
+        self.update_probabilities();
+    }
+
+    fn record(&mut self, action: Action, position: (usize, usize)) {
+        let time = Time::singleton().get_ticks_msec() - self.started_at;
+        if let Some(moves) = self.moves.as_mut() {
+            moves.push(Move { time, action, position });
+        }
+    }
+
+    // Renders result of opening cells
+    // and ends the game if it's over
+    fn show_reveal(&mut self, reveal: Reveal) {
+        match reveal {
+            Reveal::Ignored => {},
+            Reveal::Exploded((x, y)) => {
+                self.render(x, y);
 
-                // godot_print!("{}, {}", nx, ny);
-                
-                stack.push((nx, ny));
+                // Practice game waits for undo or give up.
+                // Other mines stay hidden until then
+                if self.undo.is_some() {
+                    self.disable_buttons();
+                    self.base_mut().emit_signal("mine_hit", &[]);
+                } else {
+                    self.on_lose_game();
+                }
+            },
+            Reveal::Survived { exploded, opened } => {
+                for (x, y) in exploded {
+                    self.render(x, y);
+                }
+
+                // Exploded mines count as flagged, so
+                // flags counter goes down as well
+                let penalty = self.playback.is_none();
+                self.base_mut().emit_signal("life_lost", &[penalty.to_variant()]);
+                self.base_mut().emit_signal("change_flags", &[]);
+                self.show_opened(opened);
+            },
+            Reveal::Opened(opened) => self.show_opened(opened),
+        }
+    }
+
+    fn show_opened(&mut self, opened: Vec<(usize, usize)>) {
+        for (x, y) in opened {
+            self.render(x, y);
+
+            // Replay doesn't earn any score
+            if self.board.tile(x, y).mines_around != 0 && self.playback.is_none() {
+                self.base_mut().emit_signal("change_score", &[]);
             }
-        } 
-        // godot_print!("Found {}, mines {}", self.cells_opened, self.mines_amount);
-        
-        if self.cells_opened == (self.grid_size * self.grid_size) as u32 - self.mines_amount {
+        }
+
+        if self.board.status() == Status::Won {
             self.on_win_game();
-            return;
+        }
+    }
+
+    // Replay of the game which just ended.
+    // None for continued games as moves before
+    // save are not known
+    pub fn replay(&self) -> Option<Replay> {
+        self.moves.as_ref().map(|moves| Replay::record(&self.board, moves.clone()))
+    }
+
+    // Plays replay on this grid. Player can't click
+    // cells until it's over and replay_finished is emitted
+    pub fn start_playback(&mut self, replay: &Replay, speed: f64) {
+        self.board = replay.board();
+        self.width = self.board.width() as i32;
+        self.height = self.board.height() as i32;
+        self.mines_amount = self.board.mines_amount() as u32;
+        self.seed = replay.seed;
+        self.add_cells();
+
+        for cell in self.cells.iter_mut().flatten() {
+            cell.set_mouse_filter(MouseFilter::IGNORE);
+        }
+
+        self.moves = None;
+        self.playback = Some(Playback::new(replay, speed));
+    }
+
+    // Replay board is not a game of the player,
+    // so it must not be saved or counted
+    pub fn is_playing_back(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    pub fn flags(&self) -> i32 {
+        self.board.flags()
+    }
+
+    pub fn metrics(&self) -> Option<Metrics> {
+        self.metrics
+    }
+
+    // Counted on first call and kept, as solver
+    // plays the whole board. None before first click
+    pub fn forced_guesses(&mut self) -> Option<u32> {
+        if self.forced_guesses.is_none() {
+            let (x, y) = self.first_reveal?;
+            self.forced_guesses = Some(metrics::forced_guesses(&self.board, x, y));
+        }
+        self.forced_guesses
+    }
+
+    #[func]
+    // Metrics of the board for gdscript. Empty
+    // dictionary if mines are not placed yet
+    pub fn get_metrics(&mut self) -> Dictionary {
+        let Some(metrics) = self.metrics else {
+            return Dictionary::new();
+        };
+        dict! {
+            "3bv": metrics.three_bv,
+            "openings": metrics.openings,
+            "islands": metrics.islands,
+            "forced_guesses": self.forced_guesses().unwrap_or_default(),
         }
     }
 
     #[func]
     fn on_lose_game(&mut self) {
+        // Shows every mine and
+        // crosses out wrong flags
+        self.render_all();
         self.disable_buttons();
-        self.base_mut().emit_signal("lose_game", &[]);
+
+        // Replay ending is not a real game over,
+        // process will tell Main when replay is done
+        if self.playback.is_none() {
+            self.base_mut().emit_signal("lose_game", &[]);
+        }
     }
 
     #[func]
     fn on_win_game(&mut self) {
+        // Board flagged mines that were left
+        self.render_all();
+        self.base_mut().emit_signal("change_flags", &[]);
         self.disable_buttons();
-        self.base_mut().emit_signal("win_game", &[]);
+        if self.playback.is_none() {
+            self.base_mut().emit_signal("win_game", &[]);
+        }
     }
 
     #[func]
@@ -325,45 +957,123 @@ impl CellGrid {
         }
 
         // Reseting fields of CellGrid structure
-        self.grid_size = 10;
+        self.width = 10;
+        self.height = 10;
         self.mines_amount = 10;
-        self.cells_opened = 0;
+        self.seed = rand::random();
         self.cells = vec![];
-        self.opened = vec![];
-        self.flags = 0;
-    }
-    
-    #[func]
-    fn add_flag(&mut self) {
-        // godot_print!("+ flag");
-        self.flags += 1;
-        self.base_mut().emit_signal("change_flags", &[]);
-    }
-
-    #[func]
-    fn sub_flag(&mut self) {
-        // godot_print!("- flag");
-        self.flags -= 1;
-        self.base_mut().emit_signal("change_flags", &[]);
+        self.ghosts.clear();
+        self.board = Board::new(0, 0, &HashSet::new());
+        self.moves = None;
+        self.playback = None;
+        self.metrics = None;
+        self.first_reveal = None;
+        self.forced_guesses = None;
+        self.undo = None;
+        self.undos = 0;
+        self.cursor = None;
+        self.cursor_repeat = None;
     }
 }
 
 #[godot_api]
-impl IGridContainer for CellGrid {
-    fn init(base: Base<GridContainer>) -> Self {
+impl IControl for CellGrid {
+    fn init(base: Base<Control>) -> Self {
         Self {
             cell_scene: PackedScene::new_gd(),
-            grid_size: 10,
+            width: 10,
+            height: 10,
             cell_size: 30.0,
             mines_amount: 10,
-            cells_opened: 0,
-            flags: 0,
+            seed: rand::random(),
+            first_click: FirstClick::Safe,
+            no_guess: false,
+            max_mines: 1,
+            lives: 1,
+            topology: Topology::SQUARE,
+            question_marks: false,
+            open_questions: false,
+            practice: false,
+            undo: None,
+            undos: 0,
             cells: vec![],
-            opened: vec![],
+            ghosts: HashMap::new(),
+            board: Board::new(0, 0, &HashSet::new()),
+            moves: None,
+            started_at: 0,
+            playback: None,
+            metrics: None,
+            first_reveal: None,
+            forced_guesses: None,
+            show_probabilities: false,
+            cursor: None,
+            cursor_repeat: None,
+            menu_open: false,
             base
         }
     }
 
+    fn process(&mut self, delta: f64) {
+        self.repeat_cursor(delta);
+
+        let Some(playback) = self.playback.as_mut() else {
+            return;
+        };
+
+        let moves = playback.advance(delta).to_vec();
+        let finished = playback.is_finished();
+        for m in moves {
+            self.apply(m.action, m.position);
+        }
+
+        if finished {
+            self.playback = None;
+            self.base_mut().emit_signal("replay_finished", &[]);
+        }
+    }
+
+    // Handled before gui so buttons in hud that keep
+    // focus after click don't take arrows and space.
+    // Text fields are hidden while game is on
+    fn input(&mut self, event: Gd<InputEvent>) {
+        if !self.accepts_keys() {
+            return;
+        }
+
+        // Held direction is repeated in process, so key echo
+        // and every stick motion past deadzone are skipped
+        let pressed = |action: &str| event.is_action_pressed_ex(action).allow_echo(true).done();
+        let direction = CURSOR_DIRECTIONS.into_iter().find(|(action, _)| pressed(action));
+
+        if let Some((action, direction)) = direction {
+            let held = self.cursor_repeat.is_some_and(|repeat| repeat.action == action);
+            if !event.is_echo() && !held {
+                self.move_cursor(direction.0, direction.1);
+                self.cursor_repeat = Some(CursorRepeat {
+                    action,
+                    direction,
+                    wait: CURSOR_DELAY,
+                    delay: CURSOR_DELAY,
+                });
+            }
+        } else if ["cursor_reveal", "cursor_flag", "cursor_chord"].into_iter().any(|action| event.is_action_pressed(action)) {
+            match self.cursor {
+                // Cell under cursor is shown first
+                // so player knows what keys act on
+                None => self.move_cursor(0, 0),
+                Some(position) if event.is_action_pressed("cursor_reveal") => self.open_at(position),
+                Some(position) if event.is_action_pressed("cursor_flag") => self.flag_at(position),
+                Some(position) => self.apply(Action::Chord, position),
+            }
+        } else {
+            return;
+        }
+
+        if let Some(mut viewport) = self.base().get_viewport() {
+            viewport.set_input_as_handled();
+        }
+    }
+
     fn ready(&mut self) {
         // need to load cell_scene from godot
         // in order to bind scene with our template
@@ -1,8 +1,18 @@
-use godot::{classes::{Button, CanvasLayer, ICanvasLayer, Label, OptionButton}, obj::WithBaseField, prelude::*};
+use godot::{classes::{Button, CanvasLayer, CheckBox, Control, ICanvasLayer, InputEvent, Label, LineEdit, OptionButton, SpinBox}, obj::WithBaseField, prelude::*};
+
+use crate::{board::Board, leaderboard::Entry, stats::Statistics};
+
+// Id of "Custom..." item in Difficulty option button.
+// Other ids are used as difficulty multiplier
+pub const CUSTOM_DIFFICULTY: i32 = 3;
 
 #[derive(GodotClass)]
 #[class(base=CanvasLayer)]
 pub struct Hud {
+    // Focus is on hud buttons, so gamepad
+    // and arrows navigate them instead of grid
+    menu_open: bool,
+
     base: Base<CanvasLayer>
 }
 
@@ -11,6 +21,33 @@ impl Hud {
     #[signal]
     fn on_start_game_button_press();
 
+    #[signal]
+    fn on_continue_game_button_press();
+
+    #[signal]
+    fn on_watch_replay_button_press();
+
+    #[signal]
+    fn on_stats_button_press();
+
+    #[signal]
+    fn show_probabilities_toggled(show: bool);
+
+    #[signal]
+    fn on_hint_button_press();
+
+    #[signal]
+    fn on_undo_button_press();
+
+    #[signal]
+    fn on_give_up_button_press();
+
+    #[signal]
+    fn on_reset_stats_button_press();
+
+    #[signal]
+    fn menu_toggled(open: bool);
+
     #[func]
     pub fn show_message(&mut self, text: GString) {
         let mut label = self.base().get_node_as::<Label>("Message");
@@ -43,6 +80,144 @@ impl Hud {
         flags_label.set_text(&format!("{flags}🚩"));
     }
 
+    // Lives are shown only in lives mode,
+    // classic game has nothing to count
+    pub fn update_lives(&mut self, board: &Board) {
+        let text = if board.lives() > 1 {
+            format!("{}❤️", board.lives_left())
+        } else {
+            String::new()
+        };
+        let mut lives_label = self.base_mut().get_node_as::<Label>("LivesLeft");
+        lives_label.set_text(&text);
+    }
+
+    #[func]
+    // Triggered on start game so player
+    // can share seed of the board they are playing
+    pub fn update_seed(&mut self, seed: u64) {
+        let mut seed_label = self.base_mut().get_node_as::<Label>("Seed");
+        seed_label.set_text(&format!("Seed: {seed}"));
+    }
+
+    // Numbers mean different things on different boards,
+    // so shape, neighbourhood, wrap and multi-mine cells are shown
+    pub fn update_mode(&mut self, board: &Board) {
+        let mut mode = format!("Mode: {}", board.topology());
+        if board.max_mines() > 1 {
+            mode += &format!(", up to {} mines in cell", board.max_mines());
+        }
+        let mut mode_label = self.base_mut().get_node_as::<Label>("Mode");
+        mode_label.set_text(&mode);
+
+        let multi_mines = board.max_mines() > 1;
+        self.set_solver_disabled("Hint", multi_mines);
+        self.set_solver_disabled("ShowProbabilities", multi_mines);
+    }
+
+    // Solver can't play cells with several mines, so
+    // there are no hints or probabilities for them
+    fn set_solver_disabled(&mut self, node: &str, disabled: bool) {
+        let mut button = self.base().get_node_as::<Button>(node);
+        button.set_disabled(disabled);
+        let tooltip = if disabled { "Not available with multi-mine cells" } else { "" };
+        button.set_tooltip_text(tooltip);
+    }
+
+    // Seed typed by player. None if field is empty
+    // or has something that is not a number,
+    // then CellGrid will pick random one
+    pub fn seed(&self) -> Option<u64> {
+        let seed_input = self.base().get_node_as::<LineEdit>("SeedInput");
+        seed_input.get_text().to_string().trim().parse().ok()
+    }
+
+    #[func]
+    // If checked first click always opens
+    // empty area, not just one safe cell
+    pub fn safe_opening(&self) -> bool {
+        let safe_opening = self.base().get_node_as::<CheckBox>("SafeOpening");
+        safe_opening.is_pressed()
+    }
+
+    #[func]
+    // If checked board can be solved
+    // without guessing
+    pub fn no_guess(&self) -> bool {
+        let no_guess = self.base().get_node_as::<CheckBox>("NoGuess");
+        no_guess.is_pressed()
+    }
+
+    #[func]
+    // If checked click on mine can be undone.
+    // Such games have their own leaderboard
+    pub fn practice(&self) -> bool {
+        let practice = self.base().get_node_as::<CheckBox>("Practice");
+        practice.is_pressed()
+    }
+
+    #[func]
+    // If checked board is made of hexagons
+    // with 6 cells around every cell
+    pub fn hex(&self) -> bool {
+        let hex = self.base().get_node_as::<CheckBox>("Hex");
+        hex.is_pressed()
+    }
+
+    #[func]
+    // If checked edges of the board are glued
+    // together, cells on the edge see the other side
+    pub fn wrap(&self) -> bool {
+        let wrap = self.base().get_node_as::<CheckBox>("Wrap");
+        wrap.is_pressed()
+    }
+
+    #[func]
+    // If checked cells can hold several
+    // mines and take as many flags
+    pub fn multi_mines(&self) -> bool {
+        let multi_mines = self.base().get_node_as::<CheckBox>("MultiMines");
+        multi_mines.is_pressed()
+    }
+
+    #[func]
+    // No guessing needs solver, which
+    // can't play multi-mine boards
+    fn on_multi_mines_toggled(&mut self, checked: bool) {
+        self.set_solver_disabled("NoGuess", checked);
+    }
+
+    #[func]
+    // Mines player can open before the game is lost
+    pub fn lives(&self) -> i32 {
+        let lives = self.base().get_node_as::<SpinBox>("Lives");
+        lives.get_value() as i32
+    }
+
+    #[func]
+    // Id of picked neighbourhood, it's
+    // the index in Neighbourhood::ALL
+    pub fn neighbourhood(&self) -> i32 {
+        let neighbourhood = self.base().get_node_as::<OptionButton>("Neighbourhood");
+        neighbourhood.get_selected_id()
+    }
+
+    #[func]
+    // If checked right click on flag
+    // turns it into question mark
+    pub fn question_marks(&self) -> bool {
+        let question_marks = self.base().get_node_as::<CheckBox>("QuestionMarks");
+        question_marks.is_pressed()
+    }
+
+    #[func]
+    // If checked left click opens cells
+    // with question mark on them
+    pub fn open_questions(&self) -> bool {
+        let open_questions = self.base().get_node_as::<CheckBox>("OpenQuestions");
+        open_questions.is_pressed()
+    }
+
     #[func]
     // Triggered on gameover
     // either win or lose
@@ -57,19 +232,306 @@ impl Hud {
     pub fn show_difficulty_button(&mut self) {
         let mut difficulty = self.base_mut().get_node_as::<OptionButton>("Difficulty");
         difficulty.show();
+
+        let selected = difficulty.get_selected();
+        self.on_difficulty_selected(selected as i64);
+    }
+
+    #[func]
+    // Shows width, height and mines fields
+    // only if "Custom..." is selected
+    fn on_difficulty_selected(&mut self, index: i64) {
+        let difficulty = self.base().get_node_as::<OptionButton>("Difficulty");
+        let mut custom = self.base_mut().get_node_as::<Control>("Custom");
+        custom.set_visible(difficulty.get_item_id(index as i32) == CUSTOM_DIFFICULTY);
+    }
+
+    // Width, height and mines amount of custom game
+    pub fn custom_size(&self) -> (i32, i32, i32) {
+        let value = |name| self.base().get_node_as::<SpinBox>(name).get_value() as i32;
+        (value("Custom/Width"), value("Custom/Height"), value("Custom/Mines"))
+    }
+
+    #[func]
+    // Shows seed input and checkboxes for generation.
+    // Triggered on gameover either win or lose
+    pub fn show_game_options(&mut self) {
+        let mut seed_input = self.base_mut().get_node_as::<LineEdit>("SeedInput");
+        seed_input.show();
+        let mut safe_opening = self.base_mut().get_node_as::<CheckBox>("SafeOpening");
+        safe_opening.show();
+        let mut no_guess = self.base_mut().get_node_as::<CheckBox>("NoGuess");
+        no_guess.show();
+        let mut practice = self.base_mut().get_node_as::<CheckBox>("Practice");
+        practice.show();
+        let mut question_marks = self.base_mut().get_node_as::<CheckBox>("QuestionMarks");
+        question_marks.show();
+        let mut open_questions = self.base_mut().get_node_as::<CheckBox>("OpenQuestions");
+        open_questions.show();
+        let mut hex = self.base_mut().get_node_as::<CheckBox>("Hex");
+        hex.show();
+        let mut wrap = self.base_mut().get_node_as::<CheckBox>("Wrap");
+        wrap.show();
+        let mut neighbourhood = self.base_mut().get_node_as::<OptionButton>("Neighbourhood");
+        neighbourhood.show();
+        let mut multi_mines = self.base_mut().get_node_as::<CheckBox>("MultiMines");
+        multi_mines.show();
+        let mut lives = self.base_mut().get_node_as::<SpinBox>("Lives");
+        lives.show();
+        let mut player_name = self.base_mut().get_node_as::<LineEdit>("PlayerName");
+        player_name.show();
+        let mut stats = self.base_mut().get_node_as::<Button>("Stats");
+        stats.show();
+    }
+
+    // Name written to leaderboard on win
+    pub fn player_name(&self) -> String {
+        let player_name = self.base().get_node_as::<LineEdit>("PlayerName");
+        let name = player_name.get_text().to_string();
+        match name.trim() {
+            "" => "Player".to_string(),
+            name => name.to_string(),
+        }
+    }
+
+    // Triggered on gameover. Lists best games of the board
+    // that was played, new record is marked with arrow
+    pub fn show_leaderboard(&mut self, board: &str, entries: &[Entry], new_record: Option<usize>) {
+        let mut text = format!("Best games {board}\n");
+        if entries.is_empty() {
+            text += "No wins yet\n";
+        }
+        for (place, entry) in entries.iter().enumerate() {
+            let mark = if Some(place) == new_record { "▶" } else { " " };
+            // Seed with first click gives the board back
+            let start = entry.start.map_or(String::new(), |start| format!(" first click {start}"));
+            text += &format!(
+                "{mark}{}. {}  {}s  {}  {}  seed {}{start}\n",
+                place + 1, entry.name, entry.time, entry.score, entry.date, entry.seed,
+            );
+        }
+
+        let mut leaderboard = self.base_mut().get_node_as::<Label>("Leaderboard");
+        leaderboard.set_text(&text);
+        leaderboard.show();
+    }
+
+    #[func]
+    // Stats button opens and closes the panel.
+    // Main fills it when it's opened
+    fn on_stats_button_press(&mut self) {
+        let mut panel = self.base_mut().get_node_as::<Control>("StatsPanel");
+        if panel.is_visible() {
+            panel.hide();
+        } else {
+            self.base_mut().emit_signal("on_stats_button_press", &[]);
+        }
+    }
+
+    #[func]
+    fn on_reset_stats_button_press(&mut self) {
+        self.base_mut().emit_signal("on_reset_stats_button_press", &[]);
+    }
+
+    // One block per board that was played
+    pub fn show_stats(&mut self, statistics: &Statistics) {
+        let mut text = String::new();
+        for (board, stats) in statistics.boards() {
+            let time = |time: Option<f64>| time.map_or("-".to_string(), |time| format!("{time:.1}s"));
+            text += &format!(
+                "{board}\nPlayed {}, won {}, lost {}, win rate {:.0}%\n\
+                Streak {}, best streak {}\n\
+                Average time {}, best time {}\n\
+                Cells revealed {}\n\n",
+                stats.played(), stats.won, stats.lost, stats.win_rate() * 100.0,
+                stats.streak, stats.best_streak,
+                time(stats.average_time()), time(stats.best_time.map(f64::from)),
+                stats.cells_revealed,
+            );
+        }
+        if text.is_empty() {
+            text = "No games played yet".to_string();
+        }
+
+        let mut label = self.base_mut().get_node_as::<Label>("StatsPanel/Text");
+        label.set_text(&text);
+        let mut panel = self.base_mut().get_node_as::<Control>("StatsPanel");
+        panel.show();
+    }
+
+    #[func]
+    // Probabilities checkbox stays on screen during
+    // the game, CellGrid redraws cells right away
+    fn on_show_probabilities_toggled(&mut self, show: bool) {
+        self.base_mut().emit_signal("show_probabilities_toggled", &[show.to_variant()]);
+    }
+
+    #[func]
+    fn on_hint_button_press(&mut self) {
+        self.base_mut().emit_signal("on_hint_button_press", &[]);
+    }
+
+    // Why hinted cell is safe or a mine.
+    // Empty text hides it on new game
+    pub fn show_hint(&mut self, text: &str) {
+        let mut hint_text = self.base_mut().get_node_as::<Label>("HintText");
+        hint_text.set_text(text);
+    }
+
+    #[func]
+    // Triggered when mine is hit in practice mode
+    pub fn show_undo_buttons(&mut self) {
+        let mut undo = self.base_mut().get_node_as::<Button>("UndoMove");
+        undo.show();
+        // So space or enter takes click back
+        // when playing without mouse
+        undo.grab_focus();
+        let mut give_up = self.base_mut().get_node_as::<Button>("GiveUp");
+        give_up.show();
+    }
+
+    fn hide_undo_buttons(&mut self) {
+        let mut undo = self.base_mut().get_node_as::<Button>("UndoMove");
+        undo.hide();
+        let mut give_up = self.base_mut().get_node_as::<Button>("GiveUp");
+        give_up.hide();
+    }
+
+    #[func]
+    fn on_undo_button_press(&mut self) {
+        self.hide_undo_buttons();
+        self.hide_message();
+        self.base_mut().emit_signal("on_undo_button_press", &[]);
+    }
+
+    #[func]
+    fn on_give_up_button_press(&mut self) {
+        // Main shows lose message after this
+        self.hide_undo_buttons();
+        self.base_mut().emit_signal("on_give_up_button_press", &[]);
     }
 
     #[func]
     fn on_start_game_button_press(&mut self) {
+        // Menu is hidden before signal so Main
+        // can show it again if game can't start
+        self.hide_menu();
         self.base_mut().emit_signal("on_start_game_button_press", &[]);
+    }
+
+    #[func]
+    fn on_continue_game_button_press(&mut self) {
+        self.hide_menu();
+        self.base_mut().emit_signal("on_continue_game_button_press", &[]);
+    }
+
+    #[func]
+    // Triggered on launch if there is
+    // saved game to continue
+    pub fn show_continue_button(&mut self) {
+        let mut button = self.base_mut().get_node_as::<Button>("ContinueGame");
+        button.show();
+    }
+
+    #[func]
+    fn on_watch_replay_button_press(&mut self) {
+        self.hide_menu();
+        self.base_mut().emit_signal("on_watch_replay_button_press", &[]);
+    }
+
+    #[func]
+    // Triggered on gameover and on launch
+    // if there is replay of the last game
+    pub fn show_replay_button(&mut self) {
+        let mut button = self.base_mut().get_node_as::<Button>("WatchReplay");
+        button.show();
+        let mut speed = self.base_mut().get_node_as::<OptionButton>("ReplaySpeed");
+        speed.show();
+    }
+
+    // Id of selected speed item is the multiplier
+    pub fn replay_speed(&self) -> f64 {
+        let speed = self.base().get_node_as::<OptionButton>("ReplaySpeed");
+        speed.get_selected_id() as f64
+    }
+
+    // Start on gamepad or escape opens menu by giving focus
+    // to the first button that is shown and closes it by
+    // giving keys back to CellGrid
+    fn set_menu_open(&mut self, open: bool) {
+        if self.menu_open == open {
+            return;
+        }
+        self.menu_open = open;
+
+        if open {
+            let buttons = ["ContinueGame", "StartGame", "UndoMove", "Hint"];
+            let button = buttons.into_iter()
+                .map(|name| self.base().get_node_as::<Button>(name))
+                .find(|button| button.is_visible());
+            if let Some(mut button) = button {
+                button.grab_focus();
+            }
+        } else {
+            let focused = self.base().get_viewport().and_then(|viewport| viewport.gui_get_focus_owner());
+            if let Some(mut focused) = focused {
+                focused.release_focus();
+            }
+        }
+
+        self.base_mut().emit_signal("menu_toggled", &[open.to_variant()]);
+    }
+
+    fn hide_menu(&mut self) {
+        // Focused button is about to be hidden
+        self.set_menu_open(false);
+
         let mut button = self.base_mut().get_node_as::<Button>("StartGame");
         button.hide();
+        let mut continue_button = self.base_mut().get_node_as::<Button>("ContinueGame");
+        continue_button.hide();
         let mut difficulty = self.base_mut().get_node_as::<OptionButton>("Difficulty");
         difficulty.hide();
+        let mut custom = self.base_mut().get_node_as::<Control>("Custom");
+        custom.hide();
+        let mut seed_input = self.base_mut().get_node_as::<LineEdit>("SeedInput");
+        seed_input.hide();
+        let mut safe_opening = self.base_mut().get_node_as::<CheckBox>("SafeOpening");
+        safe_opening.hide();
+        let mut no_guess = self.base_mut().get_node_as::<CheckBox>("NoGuess");
+        no_guess.hide();
+        let mut practice = self.base_mut().get_node_as::<CheckBox>("Practice");
+        practice.hide();
+        let mut question_marks = self.base_mut().get_node_as::<CheckBox>("QuestionMarks");
+        question_marks.hide();
+        let mut open_questions = self.base_mut().get_node_as::<CheckBox>("OpenQuestions");
+        open_questions.hide();
+        let mut hex = self.base_mut().get_node_as::<CheckBox>("Hex");
+        hex.hide();
+        let mut wrap = self.base_mut().get_node_as::<CheckBox>("Wrap");
+        wrap.hide();
+        let mut neighbourhood = self.base_mut().get_node_as::<OptionButton>("Neighbourhood");
+        neighbourhood.hide();
+        let mut multi_mines = self.base_mut().get_node_as::<CheckBox>("MultiMines");
+        multi_mines.hide();
+        let mut lives = self.base_mut().get_node_as::<SpinBox>("Lives");
+        lives.hide();
+        let mut watch_replay = self.base_mut().get_node_as::<Button>("WatchReplay");
+        watch_replay.hide();
+        let mut replay_speed = self.base_mut().get_node_as::<OptionButton>("ReplaySpeed");
+        replay_speed.hide();
+        let mut player_name = self.base_mut().get_node_as::<LineEdit>("PlayerName");
+        player_name.hide();
+        let mut leaderboard = self.base_mut().get_node_as::<Label>("Leaderboard");
+        leaderboard.hide();
+        let mut stats = self.base_mut().get_node_as::<Button>("Stats");
+        stats.hide();
+        let mut stats_panel = self.base_mut().get_node_as::<Control>("StatsPanel");
+        stats_panel.hide();
     }
 
     #[func]
-    // Is triggered when start button is pressed
+    // Is triggered when start or continue button is pressed
     pub fn hide_message(&mut self) {
         let mut label = self.base().get_node_as::<Label>("Message");
         label.hide();
@@ -80,7 +542,48 @@ impl Hud {
 impl ICanvasLayer for Hud {
     fn init(base: Base<CanvasLayer>) -> Self {
         Self {
+            menu_open: false,
             base
         }
     }
+
+    // Menu keys work only while menu is shown. Text
+    // fields with focus take keys before they get here
+    fn unhandled_input(&mut self, event: Gd<InputEvent>) {
+        if event.is_action_pressed("menu") {
+            let open = !self.menu_open;
+            self.set_menu_open(open);
+            if let Some(mut viewport) = self.base().get_viewport() {
+                viewport.set_input_as_handled();
+            }
+            return;
+        }
+
+        let start_button = self.base().get_node_as::<Button>("StartGame");
+        if !start_button.is_visible() {
+            return;
+        }
+
+        let difficulties = [
+            ("difficulty_easy", 0),
+            ("difficulty_medium", 1),
+            ("difficulty_hard", 2),
+            ("difficulty_custom", CUSTOM_DIFFICULTY),
+        ];
+
+        if event.is_action_pressed("new_game") {
+            self.on_start_game_button_press();
+        } else if let Some((_, id)) = difficulties.into_iter().find(|(action, _)| event.is_action_pressed(*action)) {
+            let mut difficulty = self.base().get_node_as::<OptionButton>("Difficulty");
+            let index = difficulty.get_item_index(id);
+            difficulty.select(index);
+            self.on_difficulty_selected(index as i64);
+        } else {
+            return;
+        }
+
+        if let Some(mut viewport) = self.base().get_viewport() {
+            viewport.set_input_as_handled();
+        }
+    }
 }
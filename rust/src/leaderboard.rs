@@ -0,0 +1,369 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{board::{Board, FirstClick}, topology::Topology};
+
+// Version 2 added undos, version 1 is read as games without them.
+// Version 3 added first click, older entries don't have it
+pub const SCORES_VERSION: u32 = 3;
+
+pub const SCORES_PATH: &str = "user://scores.txt";
+
+// How many entries are kept for every board
+pub const TOP_SIZE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub time: u32,
+    pub score: u64,
+    // Date as godot gives it, YYYY-MM-DD
+    pub date: String,
+    pub seed: u64,
+    // Fatal clicks taken back in practice mode
+    pub undos: u32,
+    // Seed alone doesn't give the board back, mines
+    // are placed around the first click. None for games
+    // continued from save and entries from version 2
+    pub start: Option<Start>,
+}
+
+// First clicked cell and rules mines were placed by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Start {
+    pub position: (usize, usize),
+    pub first_click: FirstClick,
+    pub no_guess: bool,
+}
+
+impl Start {
+    pub fn of(board: &Board) -> Option<Self> {
+        Some(Self {
+            position: board.first_reveal()?,
+            first_click: board.first_click(),
+            no_guess: board.is_no_guess(),
+        })
+    }
+
+    // Written as "x,y,rule", e.g. "3,4,opening" or
+    // "0,0,safe,no-guess", "-" when it's not known
+    fn parse(field: &str) -> Option<Option<Self>> {
+        if field == "-" {
+            return Some(None);
+        }
+
+        let mut parts = field.split(',');
+        let position = (parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+        let first_click = match parts.next()? {
+            "safe" => FirstClick::Safe,
+            "opening" => FirstClick::Opening,
+            _ => return None,
+        };
+        let no_guess = match parts.next() {
+            None => false,
+            Some("no-guess") => true,
+            Some(_) => return None,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Some(Self { position, first_click, no_guess }))
+    }
+}
+
+impl fmt::Display for Start {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = self.position;
+        match self.first_click {
+            FirstClick::Safe => write!(f, "{x},{y},safe")?,
+            FirstClick::Opening => write!(f, "{x},{y},opening")?,
+        }
+        if self.no_guess {
+            write!(f, ",no-guess")?;
+        }
+        Ok(())
+    }
+}
+
+// One table of best games for every board configuration.
+// Format is plain text, name goes last as it can have spaces:
+//
+// minesweeper-scores 3
+// [10x10x10]
+// 12 30000 2026-10-18 12345 0 4,5,opening Player
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Leaderboards {
+    boards: BTreeMap<String, Vec<Entry>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScoresError {
+    NotScores,
+    UnsupportedVersion(u32),
+    Broken(&'static str),
+}
+
+impl fmt::Display for ScoresError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScoresError::NotScores => write!(f, "File is not a score table"),
+            ScoresError::UnsupportedVersion(version) => write!(f, "Scores version {version} is not supported"),
+            ScoresError::Broken(reason) => write!(f, "Scores are broken: {reason}"),
+        }
+    }
+}
+
+impl Leaderboards {
+    // Boards with the same size, mines and topology share the table
+    // no matter if they were picked from difficulty or custom.
+    // Plain square boards keep names they had before topologies
+    pub fn key(board: &Board) -> String {
+        let mut key = format!("{}x{}x{}", board.width(), board.height(), board.mines_amount());
+        if board.topology() != Topology::SQUARE {
+            key = format!("{key} {}", board.topology());
+        }
+        if board.max_mines() > 1 {
+            key = format!("{key} multi");
+        }
+        if board.lives() > 1 {
+            key = format!("{key} {} lives", board.lives());
+        }
+        key
+    }
+
+    // Games where fatal click was undone are
+    // not compared with real ones
+    pub fn practice_key(board: &Board) -> String {
+        format!("{} practice", Self::key(board))
+    }
+
+    // Faster game is better, score decides between same times.
+    // Returns place of the entry starting from 0
+    // or None if it's not good enough for the table
+    pub fn add(&mut self, key: &str, entry: Entry) -> Option<usize> {
+        let entries = self.boards.entry(key.to_string()).or_default();
+        let place = entries
+            .iter()
+            .position(|other| (entry.time, std::cmp::Reverse(entry.score)) < (other.time, std::cmp::Reverse(other.score)))
+            .unwrap_or(entries.len());
+
+        if place >= TOP_SIZE {
+            return None;
+        }
+
+        entries.insert(place, entry);
+        entries.truncate(TOP_SIZE);
+        Some(place)
+    }
+
+    pub fn top(&self, key: &str) -> &[Entry] {
+        self.boards.get(key).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ScoresError> {
+        let mut lines = text.lines();
+
+        let version = lines.next()
+            .and_then(|line| line.strip_prefix("minesweeper-scores "))
+            .ok_or(ScoresError::NotScores)?;
+        let version = version.trim().parse().map_err(|_| ScoresError::NotScores)?;
+        if !(1..=SCORES_VERSION).contains(&version) {
+            return Err(ScoresError::UnsupportedVersion(version));
+        }
+
+        let mut leaderboards = Self::default();
+        let mut key = None;
+
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                key = Some(name.to_string());
+                continue;
+            }
+
+            let key = key.as_ref().ok_or(ScoresError::Broken("entry without board"))?;
+            let fields_amount = match version {
+                1 => 5,
+                2 => 6,
+                _ => 7,
+            };
+            let mut fields = line.splitn(fields_amount, ' ');
+            let mut field = || fields.next().ok_or(ScoresError::Broken("missing field"));
+
+            let time = field()?.parse().map_err(|_| ScoresError::Broken("wrong time"))?;
+            let score = field()?.parse().map_err(|_| ScoresError::Broken("wrong score"))?;
+            let date = field()?.to_string();
+            let seed = field()?.parse().map_err(|_| ScoresError::Broken("wrong seed"))?;
+            let undos = if version == 1 {
+                0
+            } else {
+                field()?.parse().map_err(|_| ScoresError::Broken("wrong undos"))?
+            };
+            let start = if version < 3 {
+                None
+            } else {
+                Start::parse(field()?).ok_or(ScoresError::Broken("wrong first click"))?
+            };
+            let name = field()?.to_string();
+
+            leaderboards.add(key, Entry { name, time, score, date, seed, undos, start });
+        }
+
+        Ok(leaderboards)
+    }
+}
+
+impl fmt::Display for Leaderboards {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "minesweeper-scores {SCORES_VERSION}")?;
+        for (key, entries) in self.boards.iter() {
+            writeln!(f, "[{key}]")?;
+            for entry in entries {
+                let start = entry.start.map_or("-".to_string(), |start| start.to_string());
+                writeln!(
+                    f,
+                    "{} {} {} {} {} {start} {}",
+                    entry.time, entry.score, entry.date, entry.seed, entry.undos, entry.name,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::FirstClick;
+
+    use super::*;
+
+    fn entry(name: &str, time: u32, score: u64) -> Entry {
+        Entry {
+            name: name.to_string(),
+            time,
+            score,
+            date: "2026-10-18".to_string(),
+            seed: 12345,
+            undos: 0,
+            start: None,
+        }
+    }
+
+    #[test]
+    fn keys_tell_boards_apart() {
+        let mut board = Board::pending(30, 16, 99, 0, FirstClick::Safe);
+        assert_eq!(Leaderboards::key(&board), "30x16x99");
+        board.set_topology(Topology::HEX);
+        assert_eq!(Leaderboards::key(&board), "30x16x99 hex");
+        assert_eq!(Leaderboards::practice_key(&board), "30x16x99 hex practice");
+        board.set_topology(Topology { wrap: true, ..Topology::SQUARE });
+        assert_eq!(Leaderboards::key(&board), "30x16x99 square wrap");
+        board.set_max_mines(3);
+        assert_eq!(Leaderboards::key(&board), "30x16x99 square wrap multi");
+        board.set_lives(3);
+        assert_eq!(Leaderboards::key(&board), "30x16x99 square wrap multi 3 lives");
+    }
+
+    #[test]
+    fn sorted_by_time_then_score() {
+        let mut leaderboards = Leaderboards::default();
+        let key = "10x10x10";
+        assert_eq!(leaderboards.add(key, entry("slow", 60, 1000)), Some(0));
+        assert_eq!(leaderboards.add(key, entry("fast", 20, 1000)), Some(0));
+        assert_eq!(leaderboards.add(key, entry("rich", 60, 5000)), Some(1));
+
+        let names: Vec<_> = leaderboards.top(key).iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["fast", "rich", "slow"]);
+        assert!(leaderboards.top("30x16x99").is_empty());
+    }
+
+    #[test]
+    fn keeps_only_top_entries() {
+        let mut leaderboards = Leaderboards::default();
+        for time in 0..TOP_SIZE as u32 {
+            leaderboards.add("a", entry("p", time, 0));
+        }
+        assert_eq!(leaderboards.add("a", entry("late", 100, 0)), None);
+        assert_eq!(leaderboards.add("a", entry("new", 5, 0)), Some(6));
+        assert_eq!(leaderboards.top("a").len(), TOP_SIZE);
+        assert_eq!(leaderboards.top("a").last().unwrap().time, 8);
+    }
+
+    #[test]
+    fn round_trip_keeps_names_with_spaces() {
+        let mut leaderboards = Leaderboards::default();
+        leaderboards.add("10x10x10", entry("Player One", 12, 30000));
+        leaderboards.add("30x16x99", entry("x", 300, 1));
+
+        let text = leaderboards.to_string();
+        assert!(text.contains("[10x10x10]\n12 30000 2026-10-18 12345 0 - Player One\n"));
+        assert_eq!(Leaderboards::parse(&text), Ok(leaderboards));
+
+        let old = "minesweeper-scores 2\n[10x10x10]\n12 30000 2026-10-18 12345 0 Player One\n";
+        let old = Leaderboards::parse(old).unwrap();
+        assert_eq!(old.top("10x10x10"), &[entry("Player One", 12, 30000)]);
+
+        let old = "minesweeper-scores 1\n[10x10x10]\n12 30000 2026-10-18 12345 Player One\n";
+        let old = Leaderboards::parse(old).unwrap();
+        assert_eq!(old.top("10x10x10"), &[entry("Player One", 12, 30000)]);
+    }
+
+    #[test]
+    fn entry_keeps_first_click() {
+        let mut board = Board::pending(10, 10, 10, 12345, FirstClick::Opening);
+        assert_eq!(Start::of(&board), None);
+        board.reveal(4, 5);
+        let start = Start::of(&board).unwrap();
+        assert_eq!(start, Start { position: (4, 5), first_click: FirstClick::Opening, no_guess: false });
+
+        let mut leaderboards = Leaderboards::default();
+        leaderboards.add("10x10x10", Entry { start: Some(start), ..entry("Player", 12, 30000) });
+        let no_guess = Start { position: (0, 0), first_click: FirstClick::Safe, no_guess: true };
+        leaderboards.add("10x10x10", Entry { start: Some(no_guess), ..entry("Other", 13, 30000) });
+
+        let text = leaderboards.to_string();
+        assert!(text.contains("12 30000 2026-10-18 12345 0 4,5,opening Player\n"));
+        assert!(text.contains("13 30000 2026-10-18 12345 0 0,0,safe,no-guess Other\n"));
+        assert_eq!(Leaderboards::parse(&text), Ok(leaderboards));
+
+        for start in ["4,opening", "4,5,fast", "4,5,safe,maybe", "4,5,safe,no-guess,x"] {
+            let text = format!("minesweeper-scores 3\n[a]\n12 1 d 1 0 {start} name\n");
+            assert_eq!(Leaderboards::parse(&text), Err(ScoresError::Broken("wrong first click")));
+        }
+    }
+
+    #[test]
+    fn entries_belong_to_board_above_them() {
+        let text = "minesweeper-scores 3\n[9x9x10]\n12 1 d 1 0 - a\n\n[9x9x10 hex]\n15 1 d 1 0 - b\n";
+        let leaderboards = Leaderboards::parse(text).unwrap();
+        assert_eq!(leaderboards.top("9x9x10")[0].name, "a");
+        assert_eq!(leaderboards.top("9x9x10 hex")[0].name, "b");
+
+        assert_eq!(
+            Leaderboards::parse("minesweeper-scores 3\n12 1 d 1 0 - name\n"),
+            Err(ScoresError::Broken("entry without board")),
+        );
+    }
+
+    #[test]
+    fn every_version_has_its_own_fields() {
+        // Same entry written by each version, name takes
+        // the rest of the line so it has to be last
+        let lines = [(1, "12 1 d 5 Player One"), (2, "12 1 d 5 0 Player One"), (3, "12 1 d 5 0 - Player One")];
+        for (version, line) in lines {
+            let leaderboards = Leaderboards::parse(&format!("minesweeper-scores {version}\n[a]\n{line}\n")).unwrap();
+            assert_eq!(leaderboards.top("a")[0].name, "Player One");
+            assert_eq!(leaderboards.top("a")[0].seed, 5);
+        }
+
+        // Version 2 line read as version 3 has name where first click should be
+        assert_eq!(
+            Leaderboards::parse("minesweeper-scores 3\n[a]\n12 1 d 5 0 Player\n"),
+            Err(ScoresError::Broken("wrong first click")),
+        );
+        assert_eq!(
+            Leaderboards::parse("minesweeper-scores 2\n[a]\n12 1 d 5 x Player\n"),
+            Err(ScoresError::Broken("wrong undos")),
+        );
+        assert_eq!(Leaderboards::parse("minesweeper-scores 4\n"), Err(ScoresError::UnsupportedVersion(4)));
+        assert_eq!(Leaderboards::parse("scores"), Err(ScoresError::NotScores));
+    }
+}
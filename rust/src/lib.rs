@@ -1,9 +1,26 @@
+// #[godot_api] macro generates closures with big godot
+// CallError inside Result. It's not something we can fix
+// on our side, so this lint is turned off for whole crate
+#![allow(clippy::result_large_err)]
+
 use godot::prelude::*;
 
+mod board;
+// Solver bot only plays games in tests,
+// the game itself doesn't use it
+#[cfg(test)]
+mod bot;
 mod grid;
 mod cell;
 mod hud;
+mod leaderboard;
 mod main_scene;
+mod metrics;
+mod replay;
+mod save;
+mod solver;
+mod stats;
+mod topology;
 
 struct MyExtension;
 
@@ -1,6 +1,10 @@
-use godot::{classes::{OptionButton, Timer}, obj::WithBaseField, prelude::*};
+use godot::{classes::{file_access::ModeFlags, notify::NodeNotification, DirAccess, FileAccess, OptionButton, Time, Timer}, obj::WithBaseField, prelude::*};
 
-use crate::{grid::CellGrid, hud};
+use crate::{board::{Board, Status}, grid::CellGrid, hud, leaderboard::{Entry, Leaderboards, Start, SCORES_PATH}, replay::{Replay, REPLAY_PATH}, save::{SavedGame, SAVE_PATH}, stats::{Statistics, STATS_PATH}};
+
+// Every lost life takes this many base
+// scores away, hint takes just one
+const LIFE_PENALTY: u64 = 5;
 
 #[derive(GodotClass)]
 #[class(base=Node)]
@@ -15,11 +19,17 @@ struct Main {
 impl Main {
     #[func]
     fn on_lose_game(&mut self) {
+        self.save_replay();
+        self.update_stats(false);
+        self.update_leaderboard(false);
+
         let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
         let mut hud = hud.bind_mut();
         hud.show_message("You Lose".into());
         hud.show_start_button();
         hud.show_difficulty_button();
+        hud.show_game_options();
+        hud.show_replay_button();
 
         let mut timer = self.base().get_node_as::<Timer>("GameTimer");
         timer.stop();
@@ -27,10 +37,38 @@ impl Main {
 
     #[func]
     fn on_win_game(&mut self) {
+        self.save_replay();
+        self.update_stats(true);
+        let place = self.update_leaderboard(true);
+
         let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
         let mut hud = hud.bind_mut();
-        hud.show_message("You Win".into());
+        let mut message = match place {
+            Some(0) => "You Win - New record!".to_string(),
+            Some(place) => format!("You Win - #{} best game", place + 1),
+            None => "You Win".to_string(),
+        };
+
+        // Time alone can't be compared between boards,
+        // 3BV/s shows how fast board was cleared for its difficulty
+        // Forced guesses are counted only now, game is over
+        // and solver can take its time on big boards
+        let mut grid = self.base().get_node_as::<CellGrid>("CellGrid");
+        let mut grid = grid.bind_mut();
+        if let Some(metrics) = grid.metrics() {
+            message += &format!(
+                "\n3BV {} - {:.2} 3BV/s\nOpenings {}, islands {}, forced guesses {}",
+                metrics.three_bv,
+                metrics.three_bv_per_second(self.time),
+                metrics.openings,
+                metrics.islands,
+                grid.forced_guesses().unwrap_or_default(),
+            );
+        }
+        hud.show_message(message.into());
         hud.show_start_button();
+        hud.show_game_options();
+        hud.show_replay_button();
 
         let mut timer = self.base().get_node_as::<Timer>("GameTimer");
         timer.stop();
@@ -53,29 +91,374 @@ impl Main {
         // Hiding message and reseting time in hud
         let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
 
-        // difficulties: 0 - easy, 1 - medium, 2 - hard
+        // difficulties: 0 - easy, 1 - medium, 2 - hard, 3 - custom
         let difficulty = hud.get_node_as::<OptionButton>("Difficulty"); 
         let difficulty = difficulty.get_selected_id();
 
         let mut hud = hud.bind_mut();
+
+        // Custom size is checked before anything is reset
+        // so player can fix it and press start again
+        let custom_size = hud.custom_size();
+        if difficulty == hud::CUSTOM_DIFFICULTY {
+            let (width, height, mines_amount) = custom_size;
+            if let Err(err) = Board::check_size(width as usize, height as usize, mines_amount as usize) {
+                hud.show_message(err.to_string().into());
+                hud.show_start_button();
+                hud.show_difficulty_button();
+                hud.show_game_options();
+                return;
+            }
+        }
+
         hud.hide_message();
+        hud.show_hint("");
         hud.update_time(self.time);
+
+        // New game replaces saved one
+        Self::remove_save();
         
 
         // Reseting CellGrid
         let mut grid = self.base_mut().get_node_as::<CellGrid>("CellGrid");
         let mut grid = grid.bind_mut();
         grid.clear_board();
-        grid.init_grid(difficulty as f64);
 
-        // Setting flags and score for the first time
-        hud.update_flags(grid.flags);
+        // If player didn't type seed, random one
+        // from clear_board is used
+        if let Some(seed) = hud.seed() {
+            grid.set_seed(seed);
+        }
+        grid.set_safe_opening(hud.safe_opening());
+        grid.set_no_guess(hud.no_guess());
+        grid.set_question_marks(hud.question_marks(), hud.open_questions());
+        grid.set_practice(hud.practice());
+        grid.set_hex(hud.hex());
+        grid.set_wrap(hud.wrap());
+        grid.set_neighbourhood(hud.neighbourhood());
+        grid.set_multi_mines(hud.multi_mines());
+        grid.set_lives(hud.lives());
+        if difficulty == hud::CUSTOM_DIFFICULTY {
+            let (width, height, mines_amount) = custom_size;
+            grid.init_custom_grid(width, height, mines_amount);
+        } else {
+            grid.init_grid(difficulty as f64);
+        }
+
+        // Setting flags, score and seed for the first time
+        hud.update_flags(grid.flags());
+        hud.update_seed(grid.get_seed());
+        hud.update_mode(grid.board());
+        hud.update_lives(grid.board());
         hud.update_score(self.score);
     
         let mut timer = self.base().get_node_as::<Timer>("GameTimer");
         timer.start();
     }
 
+    #[func]
+    // Triggered by continue button which is
+    // shown on launch only if save exists
+    fn on_continue_game(&mut self) {
+        let text = FileAccess::get_file_as_string(SAVE_PATH).to_string();
+
+        // Save is removed right away, so finished game
+        // won't be offered again. It's written back on quit
+        Self::remove_save();
+
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+
+        let saved = match SavedGame::parse(&text) {
+            Ok(saved) => saved,
+            Err(err) => {
+                godot_warn!("Can't load save: {err}");
+                hud.show_message(err.to_string().into());
+                hud.show_start_button();
+                hud.show_difficulty_button();
+                hud.show_game_options();
+                return;
+            },
+        };
+
+        self.time = saved.time;
+        self.score = saved.score;
+
+        let mut grid = self.base_mut().get_node_as::<CellGrid>("CellGrid");
+        let mut grid = grid.bind_mut();
+        grid.clear_board();
+        grid.set_question_marks(hud.question_marks(), hud.open_questions());
+        grid.set_practice(saved.practice);
+        grid.load_board(saved.board);
+        grid.set_undos(saved.undos);
+
+        hud.hide_message();
+        hud.show_hint("");
+        hud.update_time(self.time);
+        hud.update_flags(grid.flags());
+        hud.update_seed(grid.get_seed());
+        hud.update_mode(grid.board());
+        hud.update_lives(grid.board());
+        hud.update_score(self.score);
+
+        let mut timer = self.base().get_node_as::<Timer>("GameTimer");
+        timer.start();
+    }
+
+    // Writes game in progress on disk.
+    // Games that are over or not started are not saved
+    fn save_game(&mut self) {
+        let grid = self.base().get_node_as::<CellGrid>("CellGrid");
+        let grid = grid.bind();
+
+        // Practice game waiting for undo is saved from
+        // before the click on mine, as if it was taken back
+        let (board, undos) = match grid.undo_board() {
+            Some(before) => (before, grid.undos() + 1),
+            None => (grid.board(), grid.undos()),
+        };
+        if grid.is_playing_back() || board.status() != Status::Playing || !board.is_started() {
+            return;
+        }
+
+        let saved = SavedGame {
+            board: board.clone(),
+            time: self.time,
+            score: self.score,
+            undos,
+            practice: grid.is_practice(),
+        };
+
+        match FileAccess::open(SAVE_PATH, ModeFlags::WRITE) {
+            Some(mut file) => file.store_string(&saved.to_string()),
+            None => godot_warn!("Can't write save to {SAVE_PATH}"),
+        }
+    }
+
+    // Writes replay of the game that just ended.
+    // Only the last one is kept
+    fn save_replay(&mut self) {
+        let grid = self.base().get_node_as::<CellGrid>("CellGrid");
+        let Some(replay) = grid.bind().replay() else {
+            return;
+        };
+
+        match FileAccess::open(REPLAY_PATH, ModeFlags::WRITE) {
+            Some(mut file) => file.store_string(&replay.to_string()),
+            None => godot_warn!("Can't write replay to {REPLAY_PATH}"),
+        }
+    }
+
+    // Adds won game to leaderboard of its board
+    // and shows the leaderboard. Returns place
+    // of the game if it got into the table
+    fn update_leaderboard(&mut self, won: bool) -> Option<usize> {
+        let mut leaderboards = if FileAccess::file_exists(SCORES_PATH) {
+            let text = FileAccess::get_file_as_string(SCORES_PATH).to_string();
+            Leaderboards::parse(&text).unwrap_or_else(|err| {
+                godot_warn!("Can't load scores: {err}");
+                Leaderboards::default()
+            })
+        } else {
+            Leaderboards::default()
+        };
+
+        let grid = self.base().get_node_as::<CellGrid>("CellGrid");
+        let grid = grid.bind();
+        let board = grid.board();
+        let key = if grid.undos() > 0 {
+            Leaderboards::practice_key(board)
+        } else {
+            Leaderboards::key(board)
+        };
+
+        let mut hud = self.base().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+
+        let place = if won {
+            let entry = Entry {
+                name: hud.player_name(),
+                time: self.time,
+                score: self.score,
+                date: Time::singleton().get_date_string_from_system().to_string(),
+                seed: board.seed(),
+                undos: grid.undos(),
+                start: Start::of(board),
+            };
+            leaderboards.add(&key, entry)
+        } else {
+            None
+        };
+
+        if place.is_some() {
+            match FileAccess::open(SCORES_PATH, ModeFlags::WRITE) {
+                Some(mut file) => file.store_string(&leaderboards.to_string()),
+                None => godot_warn!("Can't write scores to {SCORES_PATH}"),
+            }
+        }
+
+        hud.show_leaderboard(&key, leaderboards.top(&key), place);
+        place
+    }
+
+    // Adds finished game to stats of its board.
+    // Games with undo are not counted
+    fn update_stats(&mut self, won: bool) {
+        let grid = self.base().get_node_as::<CellGrid>("CellGrid");
+        let grid = grid.bind();
+        if grid.undos() > 0 {
+            return;
+        }
+
+        let mut statistics = Self::load_stats();
+        let board = grid.board();
+        let key = Leaderboards::key(board);
+
+        let stats = statistics.board_mut(&key);
+        if won {
+            stats.add_win(self.time, board.cells_opened());
+        } else {
+            stats.add_loss(board.cells_opened());
+        }
+
+        match FileAccess::open(STATS_PATH, ModeFlags::WRITE) {
+            Some(mut file) => file.store_string(&statistics.to_string()),
+            None => godot_warn!("Can't write stats to {STATS_PATH}"),
+        }
+    }
+
+    fn load_stats() -> Statistics {
+        if !FileAccess::file_exists(STATS_PATH) {
+            return Statistics::default();
+        }
+
+        let text = FileAccess::get_file_as_string(STATS_PATH).to_string();
+        Statistics::parse(&text).unwrap_or_else(|err| {
+            godot_warn!("Can't load stats: {err}");
+            Statistics::default()
+        })
+    }
+
+    #[func]
+    // Triggered by stats button in hud
+    fn on_show_stats(&mut self) {
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        hud.bind_mut().show_stats(&Self::load_stats());
+    }
+
+    #[func]
+    // Triggered by reset button in stats panel
+    fn on_reset_stats(&mut self) {
+        if FileAccess::file_exists(STATS_PATH) {
+            DirAccess::remove_absolute(STATS_PATH);
+        }
+
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        hud.bind_mut().show_stats(&Statistics::default());
+    }
+
+    #[func]
+    // Triggered by CellGrid when mine is
+    // clicked in practice mode
+    fn on_mine_hit(&mut self) {
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+        hud.show_message("Boom! Undo the click or give up".into());
+        hud.show_undo_buttons();
+    }
+
+    #[func]
+    // Triggered by CellGrid when mine takes one
+    // of the lives and game goes on
+    fn on_life_lost(&mut self, penalty: bool) {
+        if penalty {
+            self.score = self.score.saturating_sub(self.base_score * LIFE_PENALTY);
+        }
+
+        let grid = self.base().get_node_as::<CellGrid>("CellGrid");
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+        hud.update_lives(grid.bind().board());
+        hud.update_score(self.score);
+    }
+
+    #[func]
+    // Triggered by watch replay button
+    fn on_watch_replay(&mut self) {
+        let text = FileAccess::get_file_as_string(REPLAY_PATH).to_string();
+
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+
+        let replay = match Replay::parse(&text) {
+            Ok(replay) => replay,
+            Err(err) => {
+                godot_warn!("Can't load replay: {err}");
+                hud.show_message(err.to_string().into());
+                hud.show_start_button();
+                hud.show_difficulty_button();
+                hud.show_game_options();
+                return;
+            },
+        };
+
+        // Time and score are not played back
+        self.time = 0;
+        self.score = 0;
+
+        let mut grid = self.base_mut().get_node_as::<CellGrid>("CellGrid");
+        let mut grid = grid.bind_mut();
+        grid.clear_board();
+        grid.start_playback(&replay, hud.replay_speed());
+
+        hud.hide_message();
+        hud.show_hint("");
+        hud.update_time(self.time);
+        hud.update_flags(grid.flags());
+        hud.update_seed(grid.get_seed());
+        hud.update_mode(grid.board());
+        hud.update_lives(grid.board());
+        hud.update_score(self.score);
+    }
+
+    #[func]
+    // Triggered by CellGrid when every
+    // move of replay was played
+    fn on_replay_finished(&mut self) {
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+        hud.show_message("Replay finished".into());
+        hud.show_start_button();
+        hud.show_difficulty_button();
+        hud.show_game_options();
+        hud.show_replay_button();
+    }
+
+    fn remove_save() {
+        if FileAccess::file_exists(SAVE_PATH) {
+            DirAccess::remove_absolute(SAVE_PATH);
+        }
+    }
+
+    #[func]
+    // Triggered by hint button. Every hint
+    // takes base_score away from the score
+    fn on_hint(&mut self) {
+        let mut grid = self.base_mut().get_node_as::<CellGrid>("CellGrid");
+        let hint = grid.bind_mut().show_hint();
+
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+        match hint {
+            Some(text) => {
+                self.score = self.score.saturating_sub(self.base_score);
+                hud.update_score(self.score);
+                hud.show_hint(&text);
+            },
+            None => hud.show_hint("No hint right now"),
+        }
+    }
+
     #[func]
     // Triggered on every change_flags
     // signal trigger
@@ -84,7 +467,7 @@ impl Main {
         // Getting flags from CellGrid, which is 
         // main_scene child node
         let grid = self.base_mut().get_node_as::<CellGrid>("CellGrid");
-        let flags = grid.bind().flags;
+        let flags = grid.bind().flags();
         
         let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
         hud.bind_mut().update_flags(flags);
@@ -115,4 +498,23 @@ impl INode for Main {
             base
         }
     }
+
+    fn ready(&mut self) {
+        let mut hud = self.base_mut().get_node_as::<hud::Hud>("Hud");
+        let mut hud = hud.bind_mut();
+        if FileAccess::file_exists(SAVE_PATH) {
+            hud.show_continue_button();
+        }
+        if FileAccess::file_exists(REPLAY_PATH) {
+            hud.show_replay_button();
+        }
+    }
+
+    fn on_notification(&mut self, what: NodeNotification) {
+        // Godot quits right after this notification
+        // so game has to be saved here
+        if what == NodeNotification::WM_CLOSE_REQUEST {
+            self.save_game();
+        }
+    }
 }
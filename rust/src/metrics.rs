@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use crate::{board::{Board, Status}, solver};
+
+type Position = (usize, usize);
+
+// How hard the board is, not counting how fast it was played
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    // Least clicks needed to open every safe cell:
+    // one per opening and one per number outside of them
+    pub three_bv: u32,
+    // Connected areas of empty cells, each opens with one click
+    pub openings: u32,
+    // Connected groups of numbers that no opening reaches
+    pub islands: u32,
+}
+
+impl Metrics {
+    // 3BV per second, time is counted from 1 so
+    // instant wins don't divide by zero
+    pub fn three_bv_per_second(&self, time: u32) -> f64 {
+        self.three_bv as f64 / time.max(1) as f64
+    }
+}
+
+// Every connected group of cells passing is_part,
+// neighbours are the same as for mine counting
+fn groups(board: &Board, is_part: impl Fn(Position) -> bool) -> Vec<Vec<Position>> {
+    let mut seen = HashSet::new();
+    let mut groups = vec![];
+
+    for start in board.positions() {
+        if !is_part(start) || !seen.insert(start) {
+            continue;
+        }
+
+        let mut group = vec![];
+        let mut queue = vec![start];
+        while let Some((x, y)) = queue.pop() {
+            group.push((x, y));
+            for neighbour in board.neighbours(x, y) {
+                if is_part(neighbour) && seen.insert(neighbour) {
+                    queue.push(neighbour);
+                }
+            }
+        }
+        groups.push(group);
+    }
+
+    groups
+}
+
+// Board has to have mines placed already
+pub fn measure(board: &Board) -> Metrics {
+    let is_empty = |(x, y): Position| {
+        let tile = board.tile(x, y);
+        !tile.is_mine() && tile.mines_around == 0
+    };
+    let is_lonely_number = |(x, y): Position| {
+        let tile = board.tile(x, y);
+        !tile.is_mine() && tile.mines_around > 0 && !board.neighbours(x, y).any(is_empty)
+    };
+
+    let openings = groups(board, is_empty).len() as u32;
+    let lonely_numbers = board.positions().filter(|position| is_lonely_number(*position)).count() as u32;
+    let islands = groups(board, is_lonely_number).len() as u32;
+
+    Metrics {
+        three_bv: openings + lonely_numbers,
+        openings,
+        islands,
+    }
+}
+
+// Times solver gets stuck playing from the first click (x, y).
+// Plays fresh copy of the board with deductions. When stuck,
+// opens the safe cell solver thinks is least risky, so every
+// guess goes well and only the amount of them is counted.
+// Whole board is played, so it's slow on big ones and
+// is only counted when it's asked for, not with measure
+pub fn forced_guesses(board: &Board, x: usize, y: usize) -> u32 {
+    // Solver can't play multi-mine boards, so
+    // there is nothing to count there
+    if board.max_mines() > 1 {
+        return 0;
+    }
+
+    let mut board = board.fresh();
+    board.reveal(x, y);
+
+    let mut guesses = 0;
+    while board.status() == Status::Playing {
+        if let Some(deduction) = solver::deduce(&board) {
+            for (x, y) in deduction.mines {
+                board.toggle_flag(x, y);
+            }
+            for (x, y) in deduction.safe {
+                board.reveal(x, y);
+            }
+            continue;
+        }
+
+        let chances = solver::probabilities(&board).unwrap_or_default();
+        let chance = |position: &Position| chances.get(position).copied().unwrap_or(1.0);
+        let guess = board.positions()
+            .filter(|(x, y)| {
+                let tile = board.tile(*x, *y);
+                !tile.is_mine() && !tile.is_opened
+            })
+            .min_by(|a, b| chance(a).total_cmp(&chance(b)));
+
+        let Some((x, y)) = guess else {
+            break;
+        };
+        guesses += 1;
+        board.reveal(x, y);
+    }
+
+    guesses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::FirstClick, topology::{Neighbourhood, Topology}};
+
+    fn board(width: usize, height: usize, mines: &[Position]) -> Board {
+        Board::new(width, height, &mines.iter().copied().collect())
+    }
+
+    #[test]
+    fn one_opening_clears_board() {
+        let board = board(4, 4, &[(3, 3)]);
+        let metrics = measure(&board);
+        assert_eq!(metrics, Metrics { three_bv: 1, openings: 1, islands: 0 });
+        assert_eq!(forced_guesses(&board, 0, 0), 0);
+        assert_eq!(metrics.three_bv_per_second(0), 1.0);
+    }
+
+    #[test]
+    fn numbers_without_opening() {
+        // Every safe cell is a number and none is empty
+        //  1 * 1
+        //  2 2 2
+        //  1 * 1
+        let board = board(3, 3, &[(0, 1), (2, 1)]);
+        let metrics = measure(&board);
+        assert_eq!(metrics.openings, 0);
+        assert_eq!(metrics.three_bv, 7);
+        assert_eq!(metrics.islands, 1);
+        assert!(forced_guesses(&board, 0, 0) > 0);
+    }
+
+    #[test]
+    fn separate_openings_and_islands() {
+        // Mines split board in two openings,
+        // numbers between them touch one of them.
+        // Mine count shows right one is safe
+        //  0 2 * 2 0
+        //  0 3 * 3 0
+        //  0 2 * 2 0
+        let board = board(5, 3, &[(0, 2), (1, 2), (2, 2)]);
+        let metrics = measure(&board);
+        assert_eq!(metrics.openings, 2);
+        assert_eq!(metrics.islands, 0);
+        assert_eq!(metrics.three_bv, 2);
+        assert_eq!(forced_guesses(&board, 0, 0), 0);
+        assert_eq!(metrics.three_bv_per_second(4), 0.5);
+    }
+
+    #[test]
+    fn guesses_follow_topology() {
+        // Board solver finishes without guessing
+        // needs no guesses, whatever its neighbours are
+        let knight = Topology { neighbourhood: Neighbourhood::KNIGHT, ..Topology::SQUARE };
+        let mut solvable = 0;
+        for topology in [knight, Topology::HEX, Topology { wrap: true, ..Topology::SQUARE }] {
+            for seed in 0..10 {
+                let mut board = Board::pending(9, 9, 10, seed, FirstClick::Opening);
+                board.set_topology(topology);
+                board.reveal(4, 4);
+
+                if solver::is_solvable(&board.fresh(), 4, 4) {
+                    solvable += 1;
+                    assert_eq!(forced_guesses(&board, 4, 4), 0, "{topology} seed {seed}");
+                }
+            }
+        }
+        assert!(solvable > 0);
+    }
+}
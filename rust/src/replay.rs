@@ -0,0 +1,428 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{board::{Board, MAX_HEIGHT, MAX_WIDTH, MULTI_MINES}, topology::Topology};
+
+// Bump this every time format changes, same as
+// with saves. Replays from other versions are rejected.
+// Version 2 added question marks, so version 1 can be read as is.
+// Version 3 added topology, older ones are square.
+// Version 4 added wrap, version 3 is read as is.
+// Version 5 added max-mines, older ones have 1 mine per cell.
+// Version 6 added lives, older games had 1
+pub const REPLAY_VERSION: u32 = 6;
+
+// Replay of the last finished game
+pub const REPLAY_PATH: &str = "user://last_replay.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Reveal,
+    Flag,
+    Unflag,
+    // Question mark is put on cell or
+    // taken from it, replacing flag if any
+    Question,
+    Unquestion,
+    Chord,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Reveal => "reveal",
+            Action::Flag => "flag",
+            Action::Unflag => "unflag",
+            Action::Question => "question",
+            Action::Unquestion => "unquestion",
+            Action::Chord => "chord",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reveal" => Some(Action::Reveal),
+            "flag" => Some(Action::Flag),
+            "unflag" => Some(Action::Unflag),
+            "question" => Some(Action::Question),
+            "unquestion" => Some(Action::Unquestion),
+            "chord" => Some(Action::Chord),
+            _ => None,
+        }
+    }
+}
+
+// One thing player did. Time is in milliseconds
+// since GameTimer was started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub time: u64,
+    pub action: Action,
+    pub position: (usize, usize),
+}
+
+// Board layout and every move made on it.
+// Format is plain text same as saves:
+//
+// minesweeper-replay 6
+// board 4 2
+// seed 12345
+// topology square
+// max-mines 1
+// lives 1
+// mines 0,1 1,2
+//
+// Mine is listed as many times as
+// there are mines in its cell
+// 1200 reveal 0 3
+// 2500 flag 0 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    pub topology: Topology,
+    pub max_mines: u8,
+    pub lives: u32,
+    pub mines: Vec<(usize, usize)>,
+    pub moves: Vec<Move>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    NotAReplay,
+    UnsupportedVersion(u32),
+    Broken(&'static str),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::NotAReplay => write!(f, "File is not a replay"),
+            ReplayError::UnsupportedVersion(version) => write!(f, "Replay version {version} is not supported"),
+            ReplayError::Broken(reason) => write!(f, "Replay is broken: {reason}"),
+        }
+    }
+}
+
+impl Replay {
+    // Takes mines from board after the game,
+    // so layout is the final one even if mines
+    // were placed on first click
+    pub fn record(board: &Board, moves: Vec<Move>) -> Self {
+        Self {
+            width: board.width(),
+            height: board.height(),
+            seed: board.seed(),
+            topology: board.topology(),
+            max_mines: board.max_mines(),
+            lives: board.lives(),
+            mines: board.positions()
+                .flat_map(|(x, y)| std::iter::repeat_n((x, y), board.tile(x, y).mines as usize))
+                .collect(),
+            moves,
+        }
+    }
+
+    // Fresh board with the same layout to play moves on
+    pub fn board(&self) -> Board {
+        let mut mines = HashMap::new();
+        for mine in self.mines.iter() {
+            *mines.entry(*mine).or_insert(0) += 1;
+        }
+        let mut board = Board::with_mines(self.width, self.height, self.max_mines, &mines);
+        board.set_lives(self.lives);
+        board.set_topology(self.topology);
+        board
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ReplayError> {
+        let mut lines = text.lines();
+
+        let version = lines.next()
+            .and_then(|line| line.strip_prefix("minesweeper-replay "))
+            .ok_or(ReplayError::NotAReplay)?;
+        let version = version.trim().parse().map_err(|_| ReplayError::NotAReplay)?;
+        if !(1..=REPLAY_VERSION).contains(&version) {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let mut field = |name: &str| {
+            lines.next()
+                .and_then(|line| line.strip_prefix(name))
+                .map(str::trim)
+                .ok_or(ReplayError::Broken("missing field"))
+        };
+
+        let size: Vec<usize> = field("board ")?
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| ReplayError::Broken("wrong board size"))?;
+        // Same bounds as saves, grid can't lay out
+        // a board with no rows or a huge one
+        let [width @ 1..=MAX_WIDTH, height @ 1..=MAX_HEIGHT] = size[..] else {
+            return Err(ReplayError::Broken("wrong board size"));
+        };
+        let seed = field("seed ")?.parse().map_err(|_| ReplayError::Broken("wrong seed"))?;
+        let topology = if version < 3 {
+            Topology::SQUARE
+        } else {
+            Topology::from_name(field("topology ")?).ok_or(ReplayError::Broken("wrong topology"))?
+        };
+        let max_mines = if version < 5 {
+            1
+        } else {
+            field("max-mines ")?
+                .parse()
+                .ok()
+                .filter(|max_mines| (1..=MULTI_MINES).contains(max_mines))
+                .ok_or(ReplayError::Broken("wrong max-mines"))?
+        };
+        let lives = if version < 6 {
+            1
+        } else {
+            field("lives ")?.parse().map_err(|_| ReplayError::Broken("wrong lives"))?
+        };
+
+        let on_board = |(x, y): (usize, usize)| x < height && y < width;
+        let mines = field("mines")?
+            .split_whitespace()
+            .map(|mine| {
+                let (x, y) = mine.split_once(',')?;
+                Some((x.parse().ok()?, y.parse().ok()?)).filter(|mine| on_board(*mine))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ReplayError::Broken("wrong mine"))?;
+        // Same cell is listed once for every mine in it
+        let mut stacked = HashMap::new();
+        for mine in mines.iter() {
+            let count = stacked.entry(*mine).or_insert(0);
+            *count += 1;
+            if *count > max_mines {
+                return Err(ReplayError::Broken("too many mines in cell"));
+            }
+        }
+
+        let moves = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let [time, action, x, y] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                    return None;
+                };
+                let position = (x.parse().ok()?, y.parse().ok()?);
+                on_board(position).then_some(Move {
+                    time: time.parse().ok()?,
+                    action: Action::from_name(action)?,
+                    position,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ReplayError::Broken("wrong move"))?;
+
+        Ok(Self { width, height, seed, topology, max_mines, lives, mines, moves })
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "minesweeper-replay {REPLAY_VERSION}")?;
+        writeln!(f, "board {} {}", self.width, self.height)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "topology {}", self.topology)?;
+        writeln!(f, "max-mines {}", self.max_mines)?;
+        writeln!(f, "lives {}", self.lives)?;
+
+        write!(f, "mines")?;
+        for (x, y) in self.mines.iter() {
+            write!(f, " {x},{y}")?;
+        }
+        writeln!(f)?;
+
+        for m in self.moves.iter() {
+            writeln!(f, "{} {} {} {}", m.time, m.action.name(), m.position.0, m.position.1)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Clock that hands out moves of replay
+// when their time comes. Speed 2.0 plays
+// twice as fast as the game was played
+#[derive(Debug, Clone)]
+pub struct Playback {
+    moves: Vec<Move>,
+    speed: f64,
+    elapsed: f64,
+    next: usize,
+}
+
+impl Playback {
+    pub fn new(replay: &Replay, speed: f64) -> Self {
+        Self {
+            moves: replay.moves.clone(),
+            speed,
+            elapsed: 0.0,
+            next: 0,
+        }
+    }
+
+    // Moves clock by delta seconds and returns
+    // every move which should happen by now
+    pub fn advance(&mut self, delta: f64) -> &[Move] {
+        self.elapsed += delta * 1000.0 * self.speed;
+
+        let start = self.next;
+        while self.next < self.moves.len() && self.moves[self.next].time as f64 <= self.elapsed {
+            self.next += 1;
+        }
+        &self.moves[start..self.next]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.moves.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        Replay {
+            width: 4,
+            height: 2,
+            seed: 12345,
+            topology: Topology::SQUARE,
+            max_mines: 1,
+            lives: 1,
+            mines: vec![(0, 1), (1, 2)],
+            moves: vec![
+                Move { time: 1200, action: Action::Reveal, position: (0, 3) },
+                Move { time: 2500, action: Action::Flag, position: (0, 1) },
+                Move { time: 2600, action: Action::Unflag, position: (0, 1) },
+                Move { time: 3000, action: Action::Question, position: (1, 0) },
+                Move { time: 4000, action: Action::Chord, position: (0, 3) },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = replay().to_string();
+        assert!(text.starts_with("minesweeper-replay 6\nboard 4 2\nseed 12345\ntopology square\nmax-mines 1\nlives 1\nmines 0,1 1,2\n1200 reveal 0 3\n"));
+        assert_eq!(Replay::parse(&text), Ok(replay()));
+
+        let topology = Topology { wrap: true, ..Topology::HEX };
+        let hex = Replay { topology, ..replay() };
+        assert_eq!(Replay::parse(&hex.to_string()), Ok(hex.clone()));
+        assert_eq!(hex.board().topology(), topology);
+
+        // Cell with 3 mines is listed 3 times
+        let multi = Replay { max_mines: 3, mines: vec![(0, 1), (0, 1), (0, 1), (1, 2)], ..replay() };
+        assert_eq!(Replay::parse(&multi.to_string()), Ok(multi.clone()));
+        assert_eq!(multi.board().tile(0, 1).mines, 3);
+        assert_eq!(multi.board().tile(0, 0).mines_around, 3);
+        assert_eq!(multi.board().mines_amount(), 4);
+
+        let lives = Replay { lives: 3, ..replay() };
+        assert_eq!(Replay::parse(&lives.to_string()), Ok(lives.clone()));
+        assert_eq!(lives.board().lives(), 3);
+    }
+
+    #[test]
+    fn reads_older_versions() {
+        // Every version only added a line, older
+        // ones are read with defaults for it
+        let text = replay().to_string().replace("lives 1\n", "").replace("replay 6", "replay 5");
+        assert_eq!(Replay::parse(&text), Ok(replay()));
+        let text = text.replace("max-mines 1\n", "");
+        assert_eq!(Replay::parse(&text.replace("replay 5", "replay 4")), Ok(replay()));
+        assert_eq!(Replay::parse(&text.replace("replay 5", "replay 3")), Ok(replay()));
+        let old = text.replace("topology square\n", "");
+        assert_eq!(Replay::parse(&old.replace("replay 5", "replay 2")), Ok(replay()));
+        assert!(Replay::parse(&old.replace("replay 5", "replay 1")).is_ok());
+
+        // Version 5 needs max-mines line it doesn't have here
+        assert_eq!(Replay::parse(&text), Err(ReplayError::Broken("missing field")));
+        assert_eq!(Replay::parse(&text.replace("replay 5", "replay 7")), Err(ReplayError::UnsupportedVersion(7)));
+        assert_eq!(Replay::parse("minesweeper 1"), Err(ReplayError::NotAReplay));
+    }
+
+    #[test]
+    fn rejects_broken_moves() {
+        let text = replay().to_string();
+        let broken = [
+            "1200 jump 0 3",
+            "1200 reveal 0",
+            "1200 reveal 0 3 1",
+            "x reveal 0 3",
+            "-5 reveal 0 3",
+            // Off the 4x2 board
+            "1200 reveal 0 9",
+            "1200 reveal 2 3",
+        ];
+        for line in broken {
+            assert_eq!(Replay::parse(&text.replace("1200 reveal 0 3", line)), Err(ReplayError::Broken("wrong move")), "{line}");
+        }
+
+        // Empty lines between moves are fine
+        assert_eq!(Replay::parse(&text.replace("\n2500", "\n\n2500")), Ok(replay()));
+    }
+
+    #[test]
+    fn rejects_broken_layout() {
+        let text = replay().to_string();
+        for size in ["board 0 0", "board 4 0", "board 4 99999999999999", "board 41 2", "board 4"] {
+            assert_eq!(Replay::parse(&text.replace("board 4 2", size)), Err(ReplayError::Broken("wrong board size")));
+        }
+        assert_eq!(Replay::parse(&text.replace("seed 12345", "seed -1")), Err(ReplayError::Broken("wrong seed")));
+        assert_eq!(Replay::parse(&text.replace("square", "round")), Err(ReplayError::Broken("wrong topology")));
+        for mine in ["5,2", "0,4", "0;1", "0,"] {
+            assert_eq!(Replay::parse(&text.replace("1,2", mine)), Err(ReplayError::Broken("wrong mine")), "{mine}");
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_mines() {
+        let multi = Replay { max_mines: 3, mines: vec![(0, 1), (0, 1), (0, 1)], ..replay() }.to_string();
+        assert!(Replay::parse(&multi).is_ok());
+        assert_eq!(
+            Replay::parse(&multi.replace("0,1 0,1 0,1", "0,1 0,1 0,1 0,1")),
+            Err(ReplayError::Broken("too many mines in cell")),
+        );
+        assert_eq!(
+            Replay::parse(&replay().to_string().replace("mines 0,1", "mines 0,1 0,1")),
+            Err(ReplayError::Broken("too many mines in cell")),
+        );
+        for max_mines in ["0", "4", "300"] {
+            assert_eq!(
+                Replay::parse(&multi.replace("max-mines 3", &format!("max-mines {max_mines}"))),
+                Err(ReplayError::Broken("wrong max-mines")),
+            );
+        }
+    }
+
+    #[test]
+    fn record_takes_final_layout() {
+        let mut board = replay().board();
+        board.reveal(0, 3);
+
+        let recorded = Replay::record(&board, replay().moves);
+        let mut mines = recorded.mines.clone();
+        mines.sort();
+        assert_eq!(mines, vec![(0, 1), (1, 2)]);
+        assert_eq!(recorded.board().tile(0, 0).mines_around, 1);
+    }
+
+    #[test]
+    fn playback_follows_speed() {
+        let mut playback = Playback::new(&replay(), 2.0);
+        assert!(playback.advance(0.5).is_empty());
+        assert_eq!(playback.advance(0.2).len(), 1);
+        assert_eq!(playback.advance(0.6).len(), 2);
+        assert!(!playback.is_finished());
+        assert_eq!(playback.advance(1.0).last().unwrap().action, Action::Chord);
+        assert!(playback.is_finished());
+    }
+}
@@ -0,0 +1,428 @@
+use std::fmt;
+
+use crate::{board::{Board, Tile, MAX_HEIGHT, MAX_WIDTH, MULTI_MINES}, topology::Topology};
+
+// Bump this every time format changes and add
+// a way to read previous version in SavedGame::parse
+pub const SAVE_VERSION: u32 = 8;
+
+// Where game in progress is stored on quit
+pub const SAVE_PATH: &str = "user://save.txt";
+
+// Game in progress that can be written on disk.
+// Format is plain text so it's easy to look at:
+//
+// minesweeper 8
+// time 42
+// score 3000
+// undos 0
+// practice false
+// seed 12345
+// topology square wrap
+// max-mines 3
+// lives 3
+// board 4 2
+// .*Fo
+// oXM.
+// stacked 0,1,3,0 1,2,2,2
+//
+// Board rows use one char per cell:
+// . closed, * closed mine, F flag, M flag on mine, o opened,
+// ? question mark, Q question mark on mine, X exploded mine.
+// Cells with more than 1 mine or flag are listed
+// after them as row,column,mines,flags
+#[derive(Debug, Clone)]
+pub struct SavedGame {
+    pub board: Board,
+    pub time: u32,
+    pub score: u64,
+    // Fatal clicks taken back in practice mode
+    pub undos: u32,
+    // Practice game stays practice when continued,
+    // whatever is checked in HUD at the time
+    pub practice: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveError {
+    // File is not a save of this game at all
+    NotASave,
+    // Save was written by version of the game
+    // which format we can't read
+    UnsupportedVersion(u32),
+    // Save has right version but its content is wrong
+    Broken(&'static str),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::NotASave => write!(f, "File is not a save"),
+            SaveError::UnsupportedVersion(version) => write!(f, "Save version {version} is not supported"),
+            SaveError::Broken(reason) => write!(f, "Save is broken: {reason}"),
+        }
+    }
+}
+
+impl fmt::Display for SavedGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let board = &self.board;
+
+        writeln!(f, "minesweeper {SAVE_VERSION}")?;
+        writeln!(f, "time {}", self.time)?;
+        writeln!(f, "score {}", self.score)?;
+        writeln!(f, "undos {}", self.undos)?;
+        writeln!(f, "practice {}", self.practice)?;
+        writeln!(f, "seed {}", board.seed())?;
+        writeln!(f, "topology {}", board.topology())?;
+        writeln!(f, "max-mines {}", board.max_mines())?;
+        writeln!(f, "lives {}", board.lives())?;
+        writeln!(f, "board {} {}", board.width(), board.height())?;
+
+        for x in 0..board.height() {
+            let row: String = (0..board.width())
+                .map(|y| {
+                    let tile = board.tile(x, y);
+                    match (tile.is_opened, tile.is_flagged(), tile.is_questioned, tile.is_mine()) {
+                        (true, _, _, true) => 'X',
+                        (true, _, _, false) => 'o',
+                        (_, true, _, true) => 'M',
+                        (_, true, _, false) => 'F',
+                        (_, _, true, true) => 'Q',
+                        (_, _, true, false) => '?',
+                        (_, _, _, true) => '*',
+                        (_, _, _, false) => '.',
+                    }
+                })
+                .collect();
+            writeln!(f, "{row}")?;
+        }
+
+        write!(f, "stacked")?;
+        for (x, y) in board.positions() {
+            let tile = board.tile(x, y);
+            if tile.mines > 1 || tile.flags > 1 {
+                write!(f, " {x},{y},{},{}", tile.mines, tile.flags)?;
+            }
+        }
+        writeln!(f)?;
+
+        Ok(())
+    }
+}
+
+impl SavedGame {
+    pub fn parse(text: &str) -> Result<Self, SaveError> {
+        let mut lines = text.lines();
+
+        let version = lines.next()
+            .and_then(|line| line.strip_prefix("minesweeper "))
+            .ok_or(SaveError::NotASave)?;
+        let version = version.trim().parse().map_err(|_| SaveError::NotASave)?;
+
+        // Older versions would be migrated here
+        // before falling through to the current one
+        // Version 1 is the same but without question marks,
+        // version 2 without undos, version 3 without topology,
+        // version 4 without wrap, which is read as is,
+        // version 5 without multi-mine cells, version 6 without lives
+        // and version 7 without practice, which games with undos had on
+        match version {
+            1..=SAVE_VERSION => Self::parse_lines(lines, version),
+            version => Err(SaveError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn parse_lines<'a>(mut lines: impl Iterator<Item = &'a str>, version: u32) -> Result<Self, SaveError> {
+        let mut field = |name: &str| {
+            lines.next()
+                .and_then(|line| line.strip_prefix(name))
+                .map(str::trim)
+                .ok_or(SaveError::Broken("missing field"))
+        };
+
+        let time = field("time ")?.parse().map_err(|_| SaveError::Broken("wrong time"))?;
+        let score = field("score ")?.parse().map_err(|_| SaveError::Broken("wrong score"))?;
+        let undos = if version < 3 {
+            0
+        } else {
+            field("undos ")?.parse().map_err(|_| SaveError::Broken("wrong undos"))?
+        };
+        let practice = if version < 8 {
+            undos > 0
+        } else {
+            field("practice ")?.parse().map_err(|_| SaveError::Broken("wrong practice"))?
+        };
+        let seed = field("seed ")?.parse().map_err(|_| SaveError::Broken("wrong seed"))?;
+        let topology = if version < 4 {
+            Topology::SQUARE
+        } else {
+            Topology::from_name(field("topology ")?).ok_or(SaveError::Broken("wrong topology"))?
+        };
+        let max_mines = if version < 6 {
+            1
+        } else {
+            field("max-mines ")?
+                .parse()
+                .ok()
+                .filter(|max_mines| (1..=MULTI_MINES).contains(max_mines))
+                .ok_or(SaveError::Broken("wrong max-mines"))?
+        };
+        let lives = if version < 7 {
+            1
+        } else {
+            field("lives ")?.parse().map_err(|_| SaveError::Broken("wrong lives"))?
+        };
+        let size: Vec<usize> = field("board ")?
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| SaveError::Broken("wrong board size"))?;
+        let [width @ 1..=MAX_WIDTH, height @ 1..=MAX_HEIGHT] = size[..] else {
+            return Err(SaveError::Broken("wrong board size"));
+        };
+
+        let mut tiles = Vec::with_capacity(height);
+        for _ in 0..height {
+            let line = lines.next().ok_or(SaveError::Broken("missing row"))?;
+            let row = line.chars()
+                .map(|c| {
+                    let (is_opened, flagged, is_questioned, mined) = match c {
+                        'o' => (true, false, false, false),
+                        'X' => (true, false, false, true),
+                        'M' => (false, true, false, true),
+                        'F' => (false, true, false, false),
+                        'Q' => (false, false, true, true),
+                        '?' => (false, false, true, false),
+                        '*' => (false, false, false, true),
+                        '.' => (false, false, false, false),
+                        _ => return Err(SaveError::Broken("unknown cell")),
+                    };
+                    Ok(Tile {
+                        mines: mined as u8,
+                        is_opened,
+                        flags: flagged as u8,
+                        is_questioned,
+                        ..Default::default()
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if row.len() != width {
+                return Err(SaveError::Broken("wrong row length"));
+            }
+            tiles.push(row);
+        }
+
+        // Stacked cells have to be closed ones which
+        // rows already show as mine or flag
+        if version >= 6 {
+            let line = lines.next()
+                .and_then(|line| line.strip_prefix("stacked"))
+                .ok_or(SaveError::Broken("missing field"))?;
+            for stacked in line.split_whitespace() {
+                let numbers: Vec<usize> = stacked.split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| SaveError::Broken("wrong stacked cell"))?;
+                let [x, y, mines, flags] = numbers[..] else {
+                    return Err(SaveError::Broken("wrong stacked cell"));
+                };
+                let tile = tiles.get_mut(x)
+                    .and_then(|row| row.get_mut(y))
+                    .filter(|tile| {
+                        mines <= max_mines as usize && flags <= max_mines as usize
+                            && tile.is_mine() == (mines > 0) && tile.is_flagged() == (flags > 0)
+                    })
+                    .ok_or(SaveError::Broken("wrong stacked cell"))?;
+                tile.mines = mines as u8;
+                tile.flags = flags as u8;
+            }
+        }
+
+        // Game with every life lost is over,
+        // so it can't be in the save
+        let exploded = tiles.iter().flatten().filter(|tile| tile.is_exploded()).count();
+        if exploded >= lives as usize {
+            return Err(SaveError::Broken("no lives left"));
+        }
+
+        Ok(Self {
+            board: Board::restore(seed, topology, max_mines, lives, &tiles),
+            time,
+            score,
+            undos,
+            practice,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::Status;
+
+    use super::*;
+
+    fn game() -> SavedGame {
+        let mut board = Board::new(4, 2, &[(0, 1), (1, 2)].into_iter().collect());
+        board.reveal(0, 3);
+        board.toggle_flag(0, 1);
+        board.toggle_flag(0, 0);
+        board.toggle_question(1, 2);
+
+        SavedGame { board, time: 42, score: 3000, undos: 1, practice: true }
+    }
+
+    #[test]
+    fn writes_readable_format() {
+        assert_eq!(
+            game().to_string(),
+            "minesweeper 8\ntime 42\nscore 3000\nundos 1\npractice true\nseed 0\ntopology square\nmax-mines 1\nlives 1\nboard 4 2\nFM.o\n..Q.\nstacked\n",
+        );
+    }
+
+    #[test]
+    fn round_trip_keeps_game() {
+        let game = game();
+        let loaded = SavedGame::parse(&game.to_string()).unwrap();
+
+        assert_eq!(loaded.time, 42);
+        assert_eq!(loaded.score, 3000);
+        assert_eq!(loaded.undos, 1);
+        assert!(loaded.practice);
+        assert_eq!(loaded.board.flags(), game.board.flags());
+        assert_eq!(loaded.board.status(), Status::Playing);
+        for (x, y) in game.board.positions() {
+            assert_eq!(loaded.board.tile(x, y), game.board.tile(x, y));
+        }
+    }
+
+    #[test]
+    fn hex_board_keeps_its_numbers() {
+        let mut game = game();
+        let topology = Topology { wrap: true, ..Topology::HEX };
+        game.board.set_topology(topology);
+        let loaded = SavedGame::parse(&game.to_string()).unwrap();
+
+        assert_eq!(loaded.board.topology(), topology);
+        for (x, y) in game.board.positions() {
+            assert_eq!(loaded.board.tile(x, y), game.board.tile(x, y));
+        }
+    }
+
+    #[test]
+    fn multi_mine_cells_keep_counts() {
+        let mines = [((0, 1), 3), ((1, 2), 2)].into_iter().collect();
+        let mut board = Board::with_mines(4, 2, 3, &mines);
+        board.reveal(0, 3);
+        for _ in 0..2 {
+            board.toggle_flag(0, 1);
+        }
+        board.toggle_flag(1, 2);
+        let game = SavedGame { board, time: 42, score: 3000, undos: 0, practice: false };
+
+        let text = game.to_string();
+        assert!(text.contains("max-mines 3\nlives 1\nboard 4 2\n.M.o\n..M.\nstacked 0,1,3,2 1,2,2,1\n"));
+        let loaded = SavedGame::parse(&text).unwrap();
+        assert_eq!(loaded.board.max_mines(), 3);
+        assert_eq!(loaded.board.flags(), 2);
+        for (x, y) in game.board.positions() {
+            assert_eq!(loaded.board.tile(x, y), game.board.tile(x, y));
+        }
+
+        // Stack has to match the rows
+        assert!(SavedGame::parse(&text.replace("0,1,3,2", "0,0,3,2")).is_err());
+        assert!(SavedGame::parse(&text.replace("0,1,3,2", "0,1,4,2")).is_err());
+        assert!(SavedGame::parse(&text.replace("0,1,3,2", "0,1,3")).is_err());
+
+        // Cells can't hold more than multi-mine game allows
+        for max_mines in ["0", "4", "255"] {
+            assert_eq!(
+                SavedGame::parse(&text.replace("max-mines 3", &format!("max-mines {max_mines}"))).unwrap_err(),
+                SaveError::Broken("wrong max-mines"),
+            );
+        }
+    }
+
+    #[test]
+    fn exploded_mines_take_lives() {
+        let mut board = Board::new(4, 2, &[(0, 1), (1, 2)].into_iter().collect());
+        board.set_lives(3);
+        board.reveal(0, 1);
+        let game = SavedGame { board, time: 42, score: 3000, undos: 0, practice: false };
+
+        let text = game.to_string();
+        assert!(text.contains("lives 3\nboard 4 2\n.X..\n..*.\n"));
+        let loaded = SavedGame::parse(&text).unwrap();
+        assert_eq!(loaded.board.lives_left(), 2);
+        assert_eq!(loaded.board.flags(), game.board.flags());
+        for (x, y) in game.board.positions() {
+            assert_eq!(loaded.board.tile(x, y), game.board.tile(x, y));
+        }
+
+        // Game without lives left is over
+        assert!(SavedGame::parse(&text.replace("lives 3", "lives 1")).is_err());
+    }
+
+    #[test]
+    fn loaded_game_can_be_finished() {
+        let mut board = SavedGame::parse(&game().to_string()).unwrap().board;
+        for (x, y) in [(0, 2), (1, 0), (1, 1), (1, 3)] {
+            board.reveal(x, y);
+        }
+        board.toggle_flag(0, 0);
+        board.reveal(0, 0);
+        assert_eq!(board.status(), Status::Won);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let text = game().to_string().replace("minesweeper 8", "minesweeper 9");
+        assert_eq!(SavedGame::parse(&text).unwrap_err(), SaveError::UnsupportedVersion(9));
+
+        // Version 7 had no practice, it's on if there were undos.
+        // Version 6 had no lives, version 5 no multi-mine cells, version 4
+        // no wrap, version 3 no topology, version 2 no undos and version 1
+        // no question marks, otherwise it's the same
+        let text = game().to_string()
+            .replace("minesweeper 8", "minesweeper 7")
+            .replace("practice true\n", "");
+        assert!(SavedGame::parse(&text).unwrap().practice);
+        assert!(!SavedGame::parse(&text.replace("undos 1", "undos 0")).unwrap().practice);
+        let text = text
+            .replace("minesweeper 7", "minesweeper 6")
+            .replace("lives 1\n", "");
+        assert_eq!(SavedGame::parse(&text).unwrap().board.lives(), 1);
+        let text = text
+            .replace("minesweeper 6", "minesweeper 5")
+            .replace("max-mines 1\n", "")
+            .replace("stacked\n", "");
+        assert_eq!(SavedGame::parse(&text).unwrap().board.max_mines(), 1);
+        let text = text.replace("minesweeper 5", "minesweeper 4");
+        assert_eq!(SavedGame::parse(&text).unwrap().board.topology(), Topology::SQUARE);
+        let text = text.replace("minesweeper 4", "minesweeper 3").replace("topology square\n", "");
+        assert_eq!(SavedGame::parse(&text).unwrap().board.topology(), Topology::SQUARE);
+        let text = text.replace("minesweeper 3", "minesweeper 2").replace("undos 1\n", "");
+        assert_eq!(SavedGame::parse(&text).unwrap().undos, 0);
+        let text = text.replace("minesweeper 2", "minesweeper 1").replace('Q', "*");
+        assert!(SavedGame::parse(&text).is_ok());
+
+        assert_eq!(SavedGame::parse("hello").unwrap_err(), SaveError::NotASave);
+        assert_eq!(SavedGame::parse("").unwrap_err(), SaveError::NotASave);
+    }
+
+    #[test]
+    fn rejects_broken_saves() {
+        let text = game().to_string();
+        assert!(SavedGame::parse(&text.replace("FM.o", "FM.")).is_err());
+        assert!(SavedGame::parse(&text.replace("FM.o", "FM.x")).is_err());
+        assert!(SavedGame::parse(&text.replace("time 42", "time -1")).is_err());
+        assert_eq!(SavedGame::parse(&text.replace("practice true", "practice 1")).unwrap_err(), SaveError::Broken("wrong practice"));
+        assert!(SavedGame::parse(&text.replace("board 4 2", "board 4 3")).is_err());
+        assert!(SavedGame::parse(&text.replace("board 4 2", "board 4")).is_err());
+        for size in ["board 0 0", "board 4 99999999999999", "board 41 2"] {
+            assert_eq!(SavedGame::parse(&text.replace("board 4 2", size)).unwrap_err(), SaveError::Broken("wrong board size"));
+        }
+        assert!(SavedGame::parse(&text.replace("topology square", "topology cube")).is_err());
+    }
+}
@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+
+use crate::board::{Board, Status};
+
+type Position = (usize, usize);
+
+// Why cells of a deduction are safe or mines.
+// Flags are trusted, so wrong flag means wrong deduction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    // Number already touches as many flags as it shows,
+    // so everything else around it is safe
+    AllFlagged(Position),
+    // Number has exactly as many closed cells
+    // around it as mines it still misses
+    AllMines(Position),
+    // Closed cells around first number are all around second one too,
+    // so the cells only second one touches hold the difference
+    Subset(Position, Position),
+    // Every mine is flagged already
+    // or every closed cell left is a mine
+    MineCount,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deduction {
+    pub safe: Vec<Position>,
+    pub mines: Vec<Position>,
+    pub reason: Reason,
+}
+
+// Opened number with closed unflagged cells around it
+// and how many mines are still hidden among them
+#[derive(Debug)]
+struct Constraint {
+    position: Position,
+    cells: Vec<Position>,
+    mines: usize,
+}
+
+fn constraints(board: &Board) -> Vec<Constraint> {
+    let mut constraints = vec![];
+
+    for (x, y) in board.positions() {
+        let tile = board.tile(x, y);
+        if !tile.is_opened || tile.is_exploded() || tile.mines_around == 0 {
+            continue;
+        }
+
+        // Mine exploded in lives mode is as good as flag
+        let mut cells = vec![];
+        let mut flags = 0;
+        for (nx, ny) in board.neighbours(x, y) {
+            let neighbour = board.tile(nx, ny);
+            if neighbour.known_mines() > 0 {
+                flags += 1;
+            } else if !neighbour.is_opened {
+                cells.push((nx, ny));
+            }
+        }
+
+        // Nothing to learn from number which is done
+        // or touches more flags than it shows
+        if cells.is_empty() || flags > tile.mines_around as usize {
+            continue;
+        }
+
+        cells.sort_unstable();
+        constraints.push(Constraint {
+            position: (x, y),
+            cells,
+            mines: tile.mines_around as usize - flags,
+        });
+    }
+
+    constraints
+}
+
+// Finds one thing that can be proven from opened numbers and flags.
+// Simple rules are tried first so the reason stays easy to explain
+pub fn deduce(board: &Board) -> Option<Deduction> {
+    // Every rule here thinks of a cell as mine or not,
+    // so multi-mine boards are not solved at all
+    if board.status() != Status::Playing || board.max_mines() > 1 {
+        return None;
+    }
+
+    let constraints = constraints(board);
+
+    for constraint in constraints.iter() {
+        if constraint.mines == 0 {
+            return Some(Deduction {
+                safe: constraint.cells.clone(),
+                mines: vec![],
+                reason: Reason::AllFlagged(constraint.position),
+            });
+        }
+        if constraint.mines == constraint.cells.len() {
+            return Some(Deduction {
+                safe: vec![],
+                mines: constraint.cells.clone(),
+                reason: Reason::AllMines(constraint.position),
+            });
+        }
+    }
+
+    // Only constraints that share a cell can be subsets
+    // of each other, so they are grouped by cell
+    let mut by_cell: HashMap<Position, Vec<usize>> = HashMap::new();
+    for (i, constraint) in constraints.iter().enumerate() {
+        for cell in constraint.cells.iter() {
+            by_cell.entry(*cell).or_default().push(i);
+        }
+    }
+
+    for small in constraints.iter() {
+        for &i in by_cell[&small.cells[0]].iter() {
+            let big = &constraints[i];
+            if big.cells.len() <= small.cells.len()
+                || !small.cells.iter().all(|cell| big.cells.contains(cell))
+                || big.mines < small.mines
+            {
+                continue;
+            }
+
+            let rest: Vec<_> = big.cells.iter()
+                .filter(|cell| !small.cells.contains(cell))
+                .copied()
+                .collect();
+            let mines = big.mines - small.mines;
+
+            if mines == 0 || mines == rest.len() {
+                let (safe, mines) = if mines == 0 { (rest, vec![]) } else { (vec![], rest) };
+                return Some(Deduction {
+                    safe,
+                    mines,
+                    reason: Reason::Subset(small.position, big.position),
+                });
+            }
+        }
+    }
+
+    // Last resort is total amount of mines,
+    // it works mostly at the end of the game
+    let mut closed = vec![];
+    let mut flags = 0;
+    for (x, y) in board.positions() {
+        let tile = board.tile(x, y);
+        if tile.known_mines() > 0 {
+            flags += 1;
+        } else if !tile.is_opened {
+            closed.push((x, y));
+        }
+    }
+
+    let mines_left = board.mines_amount().checked_sub(flags)?;
+    if closed.is_empty() {
+        None
+    } else if mines_left == 0 {
+        Some(Deduction { safe: closed, mines: vec![], reason: Reason::MineCount })
+    } else if mines_left == closed.len() {
+        Some(Deduction { safe: vec![], mines: closed, reason: Reason::MineCount })
+    } else {
+        None
+    }
+}
+
+// Positions are shown to player from 1 as (row,column)
+fn show(position: Position) -> String {
+    format!("({},{})", position.0 + 1, position.1 + 1)
+}
+
+impl Deduction {
+    // Short text for player, e.g.
+    // "Safe: the 1 at (3,4) already touches a flag"
+    pub fn explain(&self, board: &Board) -> String {
+        let number = |position: Position| board.tile(position.0, position.1).mines_around;
+        let reason = match self.reason {
+            Reason::AllFlagged(position) => match number(position) {
+                1 => format!("the 1 at {} already touches a flag", show(position)),
+                n => format!("the {n} at {} already touches {n} flags", show(position)),
+            },
+            Reason::AllMines(position) => format!(
+                "the {} at {} has no other closed cells around it",
+                number(position),
+                show(position),
+            ),
+            Reason::Subset(small, big) => format!("subset rule between {} and {}", show(small), show(big)),
+            Reason::MineCount if self.mines.is_empty() => "every mine is flagged already".to_string(),
+            Reason::MineCount => "every closed cell left is a mine".to_string(),
+        };
+
+        if self.safe.is_empty() {
+            format!("Mine: {reason}")
+        } else {
+            format!("Safe: {reason}")
+        }
+    }
+}
+
+// Cell a hint points at and what to say about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub position: Position,
+    pub text: String,
+}
+
+// Proven safe cell or mine if there is one, otherwise
+// the closed cell with the lowest chance of mine.
+// None if game is over or chances can't be counted
+pub fn hint(board: &Board) -> Option<Hint> {
+    if let Some(deduction) = deduce(board) {
+        let position = *deduction.safe.first().or(deduction.mines.first())?;
+        return Some(Hint { position, text: deduction.explain(board) });
+    }
+
+    let (position, chance) = probabilities(board)?
+        .into_iter()
+        .min_by(|(a, a_chance), (b, b_chance)| a_chance.total_cmp(b_chance).then(a.cmp(b)))?;
+    Some(Hint {
+        position,
+        text: format!(
+            "Nothing is certain, lowest risk guess is {} with {:.0}% chance of mine",
+            show(position),
+            chance * 100.0,
+        ),
+    })
+}
+
+// Plays board from (x, y) using only deductions.
+// Board has to have mines placed already
+pub fn is_solvable(board: &Board, x: usize, y: usize) -> bool {
+    let mut board = board.clone();
+    board.reveal(x, y);
+
+    while board.status() == Status::Playing {
+        let Some(deduction) = deduce(&board) else {
+            return false;
+        };
+
+        for (x, y) in deduction.mines {
+            board.toggle_flag(x, y);
+        }
+        for (x, y) in deduction.safe {
+            board.reveal(x, y);
+        }
+    }
+
+    board.status() == Status::Won
+}
+
+// Layouts are counted one by one, which is exponential
+// in the worst case. Boards that need more steps than
+// this give up instead of freezing the game
+const MAX_STEPS: usize = 1_000_000;
+
+// Frontier cells that are tied together by numbers.
+// solutions[k] is how many layouts put k mines in the group
+// and cell_solutions[i][k] how many of them have mine on cells[i].
+// Both are scaled down by the same amount so they don't overflow
+struct Group {
+    cells: Vec<Position>,
+    solutions: Vec<f64>,
+    cell_solutions: Vec<Vec<f64>>,
+}
+
+// Goes through every mine layout of one group,
+// dropping branches that already break some number
+struct Search<'a> {
+    constraints: Vec<&'a Constraint>,
+    // Constraints every cell of the group is part of
+    cell_constraints: Vec<Vec<usize>>,
+    mines_in: Vec<usize>,
+    unknown_in: Vec<usize>,
+    layout: Vec<bool>,
+    group: Group,
+    steps: usize,
+}
+
+impl Search<'_> {
+    fn run(&mut self, i: usize, mines: usize) -> bool {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return false;
+        }
+
+        if i == self.layout.len() {
+            self.group.solutions[mines] += 1.0;
+            for (cell, _) in self.layout.iter().enumerate().filter(|(_, mine)| **mine) {
+                self.group.cell_solutions[cell][mines] += 1.0;
+            }
+            return true;
+        }
+
+        for mine in [false, true] {
+            self.layout[i] = mine;
+            for &c in self.cell_constraints[i].iter() {
+                self.unknown_in[c] -= 1;
+                self.mines_in[c] += mine as usize;
+            }
+
+            let fits = self.cell_constraints[i].iter().all(|&c| {
+                let need = self.constraints[c].mines;
+                self.mines_in[c] <= need && self.mines_in[c] + self.unknown_in[c] >= need
+            });
+            let finished = !fits || self.run(i + 1, mines + mine as usize);
+
+            for &c in self.cell_constraints[i].iter() {
+                self.unknown_in[c] += 1;
+                self.mines_in[c] -= mine as usize;
+            }
+            if !finished {
+                return false;
+            }
+        }
+        self.layout[i] = false;
+
+        true
+    }
+}
+
+fn group(cells: Vec<Position>, constraints: Vec<&Constraint>, steps: &mut usize) -> Option<Group> {
+    let index: HashMap<Position, usize> = cells.iter().enumerate().map(|(i, cell)| (*cell, i)).collect();
+    let mut cell_constraints = vec![vec![]; cells.len()];
+    for (c, constraint) in constraints.iter().enumerate() {
+        for cell in constraint.cells.iter() {
+            cell_constraints[index[cell]].push(c);
+        }
+    }
+
+    let mut search = Search {
+        mines_in: vec![0; constraints.len()],
+        unknown_in: constraints.iter().map(|constraint| constraint.cells.len()).collect(),
+        layout: vec![false; cells.len()],
+        group: Group {
+            solutions: vec![0.0; cells.len() + 1],
+            cell_solutions: vec![vec![0.0; cells.len() + 1]; cells.len()],
+            cells,
+        },
+        constraints,
+        cell_constraints,
+        steps: *steps,
+    };
+    let finished = search.run(0, 0);
+    *steps = search.steps;
+    if !finished {
+        return None;
+    }
+
+    let mut group = search.group;
+    let scale = group.solutions.iter().copied().fold(0.0, f64::max);
+    if scale == 0.0 {
+        return None;
+    }
+    for value in group.solutions.iter_mut().chain(group.cell_solutions.iter_mut().flatten()) {
+        *value /= scale;
+    }
+    Some(group)
+}
+
+// Ways to pick k mines out of n cells for every k,
+// with what's left after each group picked its mines
+fn combine(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut result = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i + j] += x * y;
+        }
+    }
+    result
+}
+
+// Exact chance of mine for every closed unflagged cell, counting
+// every layout that fits opened numbers, flags and mines left.
+// Flags are trusted same as in deduce. None if game is over,
+// flags can't be right or there are too many layouts to count
+pub fn probabilities(board: &Board) -> Option<HashMap<Position, f64>> {
+    // Layouts are counted with one mine per cell,
+    // same as deduce this doesn't work for multi-mine boards
+    if board.status() != Status::Playing || board.max_mines() > 1 {
+        return None;
+    }
+
+    let constraints = constraints(board);
+    let mut by_cell: HashMap<Position, Vec<usize>> = HashMap::new();
+    for (i, constraint) in constraints.iter().enumerate() {
+        for cell in constraint.cells.iter() {
+            by_cell.entry(*cell).or_default().push(i);
+        }
+    }
+
+    // Frontier is split in groups that don't share any number,
+    // so layouts of each one can be counted separately
+    let mut groups = vec![];
+    let mut seen = vec![false; constraints.len()];
+    let mut steps = 0;
+    for start in 0..constraints.len() {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+
+        let mut queue = vec![start];
+        let mut members = vec![];
+        let mut cells = vec![];
+        while let Some(i) = queue.pop() {
+            members.push(&constraints[i]);
+            for cell in constraints[i].cells.iter() {
+                if !cells.contains(cell) {
+                    cells.push(*cell);
+                }
+                for &other in by_cell[cell].iter() {
+                    if !seen[other] {
+                        seen[other] = true;
+                        queue.push(other);
+                    }
+                }
+            }
+        }
+        groups.push(group(cells, members, &mut steps)?);
+    }
+
+    let mut flags = 0;
+    let mut interior = vec![];
+    for (x, y) in board.positions() {
+        let tile = board.tile(x, y);
+        if tile.known_mines() > 0 {
+            flags += 1;
+        } else if !tile.is_opened && !by_cell.contains_key(&(x, y)) {
+            interior.push((x, y));
+        }
+    }
+    let mines_left = board.mines_amount().checked_sub(flags)?;
+
+    // weights[t] is how many ways cells away from numbers can
+    // hold the rest if frontier has t mines. Logarithms are used
+    // as these get way too big for f64 on large boards
+    let frontier: usize = groups.iter().map(|group| group.cells.len()).sum();
+    let ln_factorial: Vec<f64> = (0..=interior.len())
+        .scan(0.0, |sum, n| {
+            if n > 0 {
+                *sum += (n as f64).ln();
+            }
+            Some(*sum)
+        })
+        .collect();
+    let ln_weights: Vec<Option<f64>> = (0..=frontier)
+        .map(|t| {
+            let rest = mines_left.checked_sub(t).filter(|rest| *rest <= interior.len())?;
+            Some(ln_factorial[interior.len()] - ln_factorial[rest] - ln_factorial[interior.len() - rest])
+        })
+        .collect();
+    let max = ln_weights.iter().flatten().copied().fold(f64::NEG_INFINITY, f64::max);
+    let weights: Vec<f64> = ln_weights.iter()
+        .map(|weight| weight.map_or(0.0, |weight| (weight - max).exp()))
+        .collect();
+
+    let all = groups.iter().fold(vec![1.0], |all, group| combine(&all, &group.solutions));
+    let total: f64 = all.iter().zip(weights.iter()).map(|(ways, weight)| ways * weight).sum();
+    if total == 0.0 {
+        return None;
+    }
+
+    let mut probabilities = HashMap::new();
+
+    if !interior.is_empty() {
+        let mines: f64 = all.iter()
+            .zip(weights.iter())
+            .enumerate()
+            .map(|(t, (ways, weight))| ways * weight * mines_left.saturating_sub(t) as f64)
+            .sum();
+        let chance = mines / total / interior.len() as f64;
+        probabilities.extend(interior.into_iter().map(|cell| (cell, chance)));
+    }
+
+    for (g, group) in groups.iter().enumerate() {
+        let others = groups.iter()
+            .enumerate()
+            .filter(|(other, _)| *other != g)
+            .fold(vec![1.0], |all, (_, other)| combine(&all, &other.solutions));
+
+        // Weight of k mines in this group with
+        // every way the rest of the board can go
+        let with: Vec<f64> = (0..group.solutions.len())
+            .map(|k| others.iter().enumerate().map(|(t, ways)| ways * weights[k + t]).sum())
+            .collect();
+
+        for (cell, solutions) in group.cells.iter().zip(group.cell_solutions.iter()) {
+            let mines: f64 = solutions.iter().zip(with.iter()).map(|(ways, weight)| ways * weight).sum();
+            probabilities.insert(*cell, mines / total);
+        }
+    }
+
+    Some(probabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::FirstClick;
+
+    use super::*;
+
+    fn board(size: usize, mines: &[Position]) -> Board {
+        Board::new(size, size, &mines.iter().copied().collect())
+    }
+
+    #[test]
+    fn number_touching_flag_makes_rest_safe() {
+        let mut board = board(3, &[(0, 0)]);
+        board.reveal(1, 0);
+        board.toggle_flag(0, 0);
+
+        let deduction = deduce(&board).unwrap();
+        assert_eq!(deduction.reason, Reason::AllFlagged((1, 0)));
+        assert!(deduction.mines.is_empty());
+        assert!(deduction.safe.contains(&(0, 1)));
+        assert!(!deduction.safe.contains(&(0, 0)));
+    }
+
+    #[test]
+    fn number_with_only_mines_left() {
+        // Flood fill opens everything but top row
+        //  * * .
+        //  2 2 1
+        //  0 0 0
+        let mut board = board(3, &[(0, 0), (0, 1)]);
+        board.reveal(2, 2);
+
+        let deduction = deduce(&board).unwrap();
+        assert_eq!(deduction.reason, Reason::AllMines((1, 0)));
+        assert_eq!(deduction.mines, vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn subset_rule() {
+        // Top row is opened, mines are in the middle one
+        //  1 2 1
+        //  * . *
+        let mut board = board(3, &[(1, 0), (1, 2)]);
+        board.reveal(0, 0);
+        board.reveal(0, 1);
+        board.reveal(0, 2);
+
+        let deduction = deduce(&board).unwrap();
+        assert_eq!(deduction.reason, Reason::Subset((0, 0), (0, 1)));
+        assert!(deduction.safe.is_empty());
+        assert_eq!(deduction.mines, vec![(1, 2)]);
+    }
+
+    #[test]
+    fn mine_count_ends_game() {
+        let mut board = board(2, &[(0, 0), (1, 1)]);
+        board.reveal(0, 1);
+        board.toggle_flag(0, 0);
+        board.toggle_flag(1, 1);
+
+        let deduction = deduce(&board).unwrap();
+        assert_eq!(deduction.reason, Reason::AllFlagged((0, 1)));
+
+        let mut board = self::board(2, &[(0, 0), (1, 1)]);
+        board.toggle_flag(0, 0);
+        board.toggle_flag(1, 1);
+        let deduction = deduce(&board).unwrap();
+        assert_eq!(deduction.reason, Reason::MineCount);
+        assert_eq!(deduction.safe.len(), 2);
+    }
+
+    #[test]
+    fn solvable_and_guess_boards() {
+        // Opening in the corner shows everything
+        assert!(is_solvable(&board(4, &[(3, 3)]), 0, 0));
+
+        // 1 in the corner touches three closed cells
+        // and nothing else can be opened safely
+        assert!(!is_solvable(&board(2, &[(1, 0)]), 0, 0));
+    }
+
+    #[test]
+    fn hints_explain_themselves() {
+        let mut board = board(3, &[(0, 0)]);
+        board.reveal(1, 0);
+        board.toggle_flag(0, 0);
+        let hint = hint(&board).unwrap();
+        assert_eq!(hint.position, (0, 1));
+        assert_eq!(hint.text, "Safe: the 1 at (2,1) already touches a flag");
+
+        let mut board = self::board(3, &[(1, 0), (1, 2)]);
+        board.reveal(0, 0);
+        board.reveal(0, 1);
+        board.reveal(0, 2);
+        let hint = self::hint(&board).unwrap();
+        assert_eq!(hint.position, (1, 2));
+        assert_eq!(hint.text, "Mine: subset rule between (1,1) and (1,2)");
+
+        // Nothing can be deduced, every cell is 1 in 3
+        let mut board = self::board(2, &[(1, 0)]);
+        board.reveal(0, 0);
+        let hint = self::hint(&board).unwrap();
+        assert_eq!(hint.position, (0, 1));
+        assert_eq!(hint.text, "Nothing is certain, lowest risk guess is (1,2) with 33% chance of mine");
+    }
+
+    #[test]
+    fn probabilities_of_simple_boards() {
+        // 1 touches three closed cells
+        let mut board = board(2, &[(1, 0)]);
+        board.reveal(0, 0);
+        let chances = probabilities(&board).unwrap();
+        assert_eq!(chances.len(), 3);
+        for chance in chances.values() {
+            assert!((chance - 1.0 / 3.0).abs() < 1e-9);
+        }
+
+        //  1 2 1
+        //  * . *
+        //  . . .
+        let mut board = self::board(3, &[(1, 0), (1, 2)]);
+        board.reveal(0, 0);
+        board.reveal(0, 1);
+        board.reveal(0, 2);
+        let chances = probabilities(&board).unwrap();
+        assert_eq!(chances[&(1, 0)], 1.0);
+        assert_eq!(chances[&(1, 1)], 0.0);
+        assert_eq!(chances[&(1, 2)], 1.0);
+        assert_eq!(chances[&(2, 1)], 0.0);
+    }
+
+    #[test]
+    fn probabilities_add_up_to_mines_left() {
+        for seed in 0..10 {
+            let mut board = Board::pending(16, 16, 40, seed, FirstClick::Opening);
+            board.reveal(8, 8);
+            board.toggle_flag(0, 0);
+
+            let Some(chances) = probabilities(&board) else {
+                continue;
+            };
+            let sum: f64 = chances.values().sum();
+            assert!((sum - 39.0).abs() < 1e-6, "seed {seed}: {sum}");
+            assert!(chances.values().all(|chance| (0.0..=1.0 + 1e-9).contains(chance)));
+        }
+    }
+}
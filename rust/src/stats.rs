@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+// Version 2 moved board key to the end of the line,
+// as keys of hex, wrap and other boards have spaces.
+// Version 1 is read as is
+pub const STATS_VERSION: u32 = 2;
+
+pub const STATS_PATH: &str = "user://stats.txt";
+
+// Totals of every finished game on one board.
+// Times are in seconds and only count won games
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub won: u32,
+    pub lost: u32,
+    pub streak: u32,
+    pub best_streak: u32,
+    pub won_time: u64,
+    pub best_time: Option<u32>,
+    pub cells_revealed: u64,
+}
+
+impl Stats {
+    pub fn played(&self) -> u32 {
+        self.won + self.lost
+    }
+
+    // From 0.0 to 1.0, zero if nothing was played
+    pub fn win_rate(&self) -> f64 {
+        match self.played() {
+            0 => 0.0,
+            played => self.won as f64 / played as f64,
+        }
+    }
+
+    pub fn average_time(&self) -> Option<f64> {
+        (self.won > 0).then(|| self.won_time as f64 / self.won as f64)
+    }
+
+    pub fn add_win(&mut self, time: u32, cells_revealed: usize) {
+        self.won += 1;
+        self.streak += 1;
+        self.best_streak = self.best_streak.max(self.streak);
+        self.won_time += time as u64;
+        self.best_time = Some(self.best_time.map_or(time, |best| best.min(time)));
+        self.cells_revealed += cells_revealed as u64;
+    }
+
+    pub fn add_loss(&mut self, cells_revealed: usize) {
+        self.lost += 1;
+        self.streak = 0;
+        self.cells_revealed += cells_revealed as u64;
+    }
+}
+
+// Stats of every board, keyed same as leaderboards.
+// One line per board, "-" when there is no best time yet.
+// Key goes last, so it can have spaces:
+//
+// minesweeper-stats 2
+// 5 2 1 3 180 21 312 10x10x10
+// 1 0 1 1 40 40 200 30x16x99 hex wrap
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Statistics {
+    boards: BTreeMap<String, Stats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatsError {
+    NotStats,
+    UnsupportedVersion(u32),
+    Broken(&'static str),
+}
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatsError::NotStats => write!(f, "File is not a stats file"),
+            StatsError::UnsupportedVersion(version) => write!(f, "Stats version {version} is not supported"),
+            StatsError::Broken(reason) => write!(f, "Stats are broken: {reason}"),
+        }
+    }
+}
+
+fn number<T: FromStr>(field: &str) -> Result<T, StatsError> {
+    field.parse().map_err(|_| StatsError::Broken("wrong number"))
+}
+
+impl Statistics {
+    pub fn board_mut(&mut self, key: &str) -> &mut Stats {
+        self.boards.entry(key.to_string()).or_default()
+    }
+
+    pub fn boards(&self) -> impl Iterator<Item = (&str, &Stats)> {
+        self.boards.iter().map(|(key, stats)| (key.as_str(), stats))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, StatsError> {
+        let mut lines = text.lines();
+
+        let version = lines.next()
+            .and_then(|line| line.strip_prefix("minesweeper-stats "))
+            .ok_or(StatsError::NotStats)?;
+        let version = version.trim().parse().map_err(|_| StatsError::NotStats)?;
+        if !(1..=STATS_VERSION).contains(&version) {
+            return Err(StatsError::UnsupportedVersion(version));
+        }
+
+        let mut statistics = Self::default();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            // Key is put in front, where version 1 had it
+            let mut fields: Vec<_> = if version < 2 {
+                line.split_whitespace().collect()
+            } else {
+                line.trim().splitn(8, ' ').collect()
+            };
+            if version >= 2 {
+                fields.rotate_right(1);
+            }
+            let [key, won, lost, streak, best_streak, won_time, best_time, cells_revealed] = fields[..] else {
+                return Err(StatsError::Broken("wrong amount of fields"));
+            };
+
+            let stats = Stats {
+                won: number(won)?,
+                lost: number(lost)?,
+                streak: number(streak)?,
+                best_streak: number(best_streak)?,
+                won_time: number(won_time)?,
+                best_time: match best_time {
+                    "-" => None,
+                    time => Some(number(time)?),
+                },
+                cells_revealed: number(cells_revealed)?,
+            };
+            statistics.boards.insert(key.to_string(), stats);
+        }
+
+        Ok(statistics)
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "minesweeper-stats {STATS_VERSION}")?;
+        for (key, stats) in self.boards.iter() {
+            let best_time = stats.best_time.map_or("-".to_string(), |time| time.to_string());
+            writeln!(
+                f,
+                "{} {} {} {} {} {best_time} {} {key}",
+                stats.won, stats.lost, stats.streak, stats.best_streak, stats.won_time, stats.cells_revealed,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streaks_and_times() {
+        let mut stats = Stats::default();
+        stats.add_win(30, 90);
+        stats.add_win(20, 90);
+        stats.add_loss(15);
+        stats.add_win(40, 90);
+
+        assert_eq!(stats.played(), 4);
+        assert_eq!(stats.win_rate(), 0.75);
+        assert_eq!(stats.streak, 1);
+        assert_eq!(stats.best_streak, 2);
+        assert_eq!(stats.best_time, Some(20));
+        assert_eq!(stats.average_time(), Some(30.0));
+        assert_eq!(stats.cells_revealed, 285);
+
+        assert_eq!(Stats::default().win_rate(), 0.0);
+        assert_eq!(Stats::default().average_time(), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut statistics = Statistics::default();
+        statistics.board_mut("10x10x10").add_win(21, 90);
+        statistics.board_mut("30x16x99").add_loss(12);
+        statistics.board_mut("30x16x99 square knight wrap multi 3 lives").add_win(50, 200);
+
+        let text = statistics.to_string();
+        assert_eq!(
+            text,
+            "minesweeper-stats 2\n1 0 1 1 21 21 90 10x10x10\n0 1 0 0 0 - 12 30x16x99\n\
+             1 0 1 1 50 50 200 30x16x99 square knight wrap multi 3 lives\n",
+        );
+        assert_eq!(Statistics::parse(&text), Ok(statistics));
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let text = "minesweeper-stats 1\n10x10x10 1 0 1 1 21 21 90\n30x16x99 0 1 0 0 0 - 12\n";
+        let statistics = Statistics::parse(text).unwrap();
+        assert_eq!(statistics.boards().count(), 2);
+        assert_eq!(statistics.boards().next(), Some(("10x10x10", &Stats {
+            won: 1,
+            lost: 0,
+            streak: 1,
+            best_streak: 1,
+            won_time: 21,
+            best_time: Some(21),
+            cells_revealed: 90,
+        })));
+    }
+
+    #[test]
+    fn key_is_the_rest_of_the_line() {
+        // Everything after 7 numbers is the key, spaces
+        // included, so it's never mistaken for a number
+        let text = "minesweeper-stats 2\n1 0 1 1 21 - 90 9x9x10 hex wrap 3 lives\n";
+        let statistics = Statistics::parse(text).unwrap();
+        let (key, stats) = statistics.boards().next().unwrap();
+        assert_eq!(key, "9x9x10 hex wrap 3 lives");
+        assert_eq!(stats.best_time, None);
+        assert_eq!(stats.cells_revealed, 90);
+
+        // Line without a key or with a word where number should be
+        assert_eq!(
+            Statistics::parse("minesweeper-stats 2\n1 0 1 1 21 21 90\n"),
+            Err(StatsError::Broken("wrong amount of fields")),
+        );
+        assert_eq!(
+            Statistics::parse("minesweeper-stats 2\n1 0 1 1 21 hex 90 9x9x10\n"),
+            Err(StatsError::Broken("wrong number")),
+        );
+
+        // Version 1 had key first, keys with spaces didn't fit there
+        assert_eq!(
+            Statistics::parse("minesweeper-stats 1\n9x9x10 hex 1 0 1 1 21 21 90\n"),
+            Err(StatsError::Broken("wrong amount of fields")),
+        );
+        assert_eq!(Statistics::parse("minesweeper-stats 3\n"), Err(StatsError::UnsupportedVersion(3)));
+        assert_eq!(Statistics::parse("minesweeper 1"), Err(StatsError::NotStats));
+    }
+}
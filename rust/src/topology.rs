@@ -0,0 +1,352 @@
+use std::fmt;
+
+// Shape of cells, which decides what cells touch each other
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Shape {
+    // Classic board, 8 cells around every cell
+    #[default]
+    Square,
+    // Pointy top hexagons with 6 cells around. Rows are
+    // laid out with odd ones shifted half a cell right
+    Hex,
+}
+
+// Most offsets one neighbourhood can have,
+// as many as there are in 5x5 square
+const MAX_OFFSETS: usize = 24;
+
+// Which cells around are counted by numbers
+// and opened by flood fill. Only square cells use it,
+// hexagons always have their 6 neighbours.
+// It's just the list of offsets, so any set of cells
+// can be used and presets below are only named ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighbourhood {
+    offsets: [(i32, i32); MAX_OFFSETS],
+    len: usize,
+}
+
+impl Neighbourhood {
+    // Classic 3x3 square
+    pub const AROUND: Self = Self::new(&SQUARE);
+    // Only up, down, left and right
+    pub const CROSS: Self = Self::new(&CROSS);
+    // Cells chess knight can jump to
+    pub const KNIGHT: Self = Self::new(&KNIGHT);
+    // 5x5 square
+    pub const WIDE: Self = Self::new(&WIDE);
+
+    // Same order as in HUD list, so
+    // option id is index in this array
+    pub const ALL: [Self; 4] = [Self::AROUND, Self::CROSS, Self::KNIGHT, Self::WIDE];
+    const NAMES: [&str; 4] = ["around", "cross", "knight", "wide"];
+
+    // Offsets are (rows, columns) from the cell.
+    // Panics if there are more than MAX_OFFSETS
+    pub const fn new(offsets: &[(i32, i32)]) -> Self {
+        assert!(offsets.len() <= MAX_OFFSETS, "too many offsets in neighbourhood");
+        let mut all = [(0, 0); MAX_OFFSETS];
+        let mut i = 0;
+        while i < offsets.len() {
+            all[i] = offsets[i];
+            i += 1;
+        }
+        Self { offsets: all, len: offsets.len() }
+    }
+
+    pub fn offsets(&self) -> &[(i32, i32)] {
+        &self.offsets[..self.len]
+    }
+
+    // Presets are found by their offsets, other
+    // lists are named by offsets themselves,
+    // e.g. "offsets:-1,0/1,0" for cells above and below
+    fn name(&self) -> String {
+        match Self::ALL.iter().position(|neighbourhood| neighbourhood == self) {
+            Some(index) => Self::NAMES[index].to_string(),
+            None => {
+                let offsets: Vec<_> = self.offsets().iter().map(|(dx, dy)| format!("{dx},{dy}")).collect();
+                format!("offsets:{}", offsets.join("/"))
+            },
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        if let Some(index) = Self::NAMES.iter().position(|preset| *preset == name) {
+            return Some(Self::ALL[index]);
+        }
+
+        let offsets = name.strip_prefix("offsets:")?
+            .split('/')
+            .map(|offset| {
+                let (dx, dy) = offset.split_once(',')?;
+                Some((dx.parse().ok()?, dy.parse().ok()?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        (offsets.len() <= MAX_OFFSETS).then(|| Self::new(&offsets))
+    }
+}
+
+impl Default for Neighbourhood {
+    fn default() -> Self {
+        Self::AROUND
+    }
+}
+
+// Everything that decides which cells are around each other.
+// Board, solver and metrics only ever ask for neighbours,
+// so new rules are added here and nowhere else in the game
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub shape: Shape,
+    // Edges are glued together, so cells of the first
+    // column touch the last one and same for rows
+    pub wrap: bool,
+    pub neighbourhood: Neighbourhood,
+}
+
+// Offsets are (rows, columns) from the cell
+const SQUARE: [(i32, i32); 8] = [
+    (-1, -1), (-1, 0), (-1, 1),
+    (0, -1), (0, 1),
+    (1, -1), (1, 0), (1, 1),
+];
+
+const CROSS: [(i32, i32); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+
+const KNIGHT: [(i32, i32); 8] = [
+    (-2, -1), (-2, 1),
+    (-1, -2), (-1, 2),
+    (1, -2), (1, 2),
+    (2, -1), (2, 1),
+];
+
+const WIDE: [(i32, i32); 24] = [
+    (-2, -2), (-2, -1), (-2, 0), (-2, 1), (-2, 2),
+    (-1, -2), (-1, -1), (-1, 0), (-1, 1), (-1, 2),
+    (0, -2), (0, -1), (0, 1), (0, 2),
+    (1, -2), (1, -1), (1, 0), (1, 1), (1, 2),
+    (2, -2), (2, -1), (2, 0), (2, 1), (2, 2),
+];
+
+// Even row sees the row above and below as shifted
+// left, odd row sees them shifted right
+const HEX_EVEN_ROW: [(i32, i32); 6] = [
+    (-1, -1), (-1, 0),
+    (0, -1), (0, 1),
+    (1, -1), (1, 0),
+];
+const HEX_ODD_ROW: [(i32, i32); 6] = [
+    (-1, 0), (-1, 1),
+    (0, -1), (0, 1),
+    (1, 0), (1, 1),
+];
+
+impl Topology {
+    pub const SQUARE: Self = Self { shape: Shape::Square, wrap: false, neighbourhood: Neighbourhood::AROUND };
+    pub const HEX: Self = Self { shape: Shape::Hex, wrap: false, neighbourhood: Neighbourhood::AROUND };
+
+    // Offsets around a cell in row x
+    fn offsets(&self, x: usize) -> &[(i32, i32)] {
+        match self.shape {
+            Shape::Square => self.neighbourhood.offsets(),
+            Shape::Hex if x % 2 == 1 => &HEX_ODD_ROW,
+            Shape::Hex => &HEX_EVEN_ROW,
+        }
+    }
+
+    // Hex rows only line up across the edge if
+    // there is even amount of them, otherwise
+    // just columns are glued together
+    pub fn wraps_rows(self, height: usize) -> bool {
+        self.wrap && (self.shape == Shape::Square || height.is_multiple_of(2))
+    }
+
+    pub fn wraps_columns(self) -> bool {
+        self.wrap
+    }
+
+    // All valid positions around (x, y) on width x height board.
+    // Positions outside of the board are skipped unless edges wrap.
+    // On boards narrower than the neighbourhood wrapping can reach
+    // the same cell twice or the cell itself, those are skipped too
+    pub fn neighbours(self, width: usize, height: usize, x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> {
+        let wrap = |position: usize, offset: i32, size: usize, wraps: bool| {
+            if wraps {
+                Some((position as i64 + offset as i64).rem_euclid(size as i64) as usize)
+            } else {
+                position.checked_add_signed(offset as isize).filter(|position| *position < size)
+            }
+        };
+        let wraps_rows = self.wraps_rows(height);
+
+        let offsets = self.offsets(x);
+        let mut neighbours = Vec::with_capacity(offsets.len());
+        for (dx, dy) in offsets {
+            let Some(nx) = wrap(x, *dx, height, wraps_rows) else {
+                continue;
+            };
+            let Some(ny) = wrap(y, *dy, width, self.wraps_columns()) else {
+                continue;
+            };
+            if (nx, ny) != (x, y) && !neighbours.contains(&(nx, ny)) {
+                neighbours.push((nx, ny));
+            }
+        }
+        neighbours.into_iter()
+    }
+
+    // Used in saves, replays and leaderboard names,
+    // e.g. "square", "hex wrap" or "square knight wrap"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut words = name.split_whitespace();
+        let mut topology = match words.next()? {
+            "square" => Self::SQUARE,
+            "hex" => Self::HEX,
+            _ => return None,
+        };
+        for word in words {
+            match word {
+                "wrap" => topology.wrap = true,
+                word => topology.neighbourhood = Neighbourhood::from_name(word)?,
+            }
+        }
+        Some(topology)
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.shape {
+            Shape::Square => write!(f, "square")?,
+            Shape::Hex => write!(f, "hex")?,
+        }
+        if self.neighbourhood != Neighbourhood::AROUND {
+            write!(f, " {}", self.neighbourhood.name())?;
+        }
+        if self.wrap {
+            write!(f, " wrap")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn around(topology: Topology, width: usize, height: usize, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let mut around: Vec<_> = topology.neighbours(width, height, x, y).collect();
+        around.sort();
+        around
+    }
+
+    #[test]
+    fn square_has_eight_neighbours() {
+        let around = around(Topology::SQUARE, 3, 3, 1, 1);
+        assert_eq!(around.len(), 8);
+        assert!(!around.contains(&(1, 1)));
+        assert_eq!(Topology::SQUARE.neighbours(3, 3, 0, 0).count(), 3);
+        assert_eq!(Topology::SQUARE.neighbours(3, 3, 0, 1).count(), 5);
+    }
+
+    #[test]
+    fn hex_rows_are_shifted() {
+        let around = |x, y| around(Topology::HEX, 5, 5, x, y);
+        assert_eq!(around(2, 2), [(1, 1), (1, 2), (2, 1), (2, 3), (3, 1), (3, 2)]);
+        assert_eq!(around(1, 2), [(0, 2), (0, 3), (1, 1), (1, 3), (2, 2), (2, 3)]);
+
+        // Cells touch each other both ways
+        for (x, y) in [(0, 0), (1, 4), (2, 2), (3, 0), (4, 4)] {
+            assert!(around(x, y).iter().all(|(nx, ny)| around(*nx, *ny).contains(&(x, y))));
+        }
+        assert_eq!(around(0, 0).len(), 2);
+        assert_eq!(around(1, 4).len(), 3);
+    }
+
+    #[test]
+    fn wrap_glues_edges() {
+        let wrap = Topology { wrap: true, ..Topology::SQUARE };
+        assert_eq!(
+            around(wrap, 4, 3, 0, 0),
+            [(0, 1), (0, 3), (1, 0), (1, 1), (1, 3), (2, 0), (2, 1), (2, 3)],
+        );
+        assert!(Topology::SQUARE.neighbours(4, 3, 0, 0).all(|(x, y)| x < 2 && y < 2));
+
+        // Two columns wrap into each other
+        // from both sides, cell is counted once
+        assert_eq!(around(wrap, 2, 3, 1, 0), [(0, 0), (0, 1), (1, 1), (2, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn hex_wraps_rows_only_when_they_line_up() {
+        let wrap = Topology { wrap: true, ..Topology::HEX };
+        assert!(wrap.wraps_rows(4));
+        assert!(!wrap.wraps_rows(5));
+
+        for height in [4, 5] {
+            let around = |x, y| around(wrap, 5, height, x, y);
+            for (x, y) in (0..height).flat_map(|x| (0..5).map(move |y| (x, y))) {
+                let edge = x == 0 || x == height - 1;
+                assert_eq!(around(x, y).len(), if edge && !wrap.wraps_rows(height) { 4 } else { 6 });
+                assert!(around(x, y).iter().all(|(nx, ny)| around(*nx, *ny).contains(&(x, y))));
+            }
+        }
+    }
+
+    #[test]
+    fn neighbourhoods_count_their_offsets() {
+        let with = |neighbourhood| Topology { neighbourhood, ..Topology::SQUARE };
+        assert_eq!(around(with(Neighbourhood::CROSS), 5, 5, 2, 2), [(1, 2), (2, 1), (2, 3), (3, 2)]);
+        assert_eq!(
+            around(with(Neighbourhood::KNIGHT), 5, 5, 2, 2),
+            [(0, 1), (0, 3), (1, 0), (1, 4), (3, 0), (3, 4), (4, 1), (4, 3)],
+        );
+        assert_eq!(around(with(Neighbourhood::KNIGHT), 5, 5, 0, 0), [(1, 2), (2, 1)]);
+        assert_eq!(around(with(Neighbourhood::WIDE), 5, 5, 2, 2).len(), 24);
+        assert_eq!(around(with(Neighbourhood::WIDE), 5, 5, 0, 0).len(), 8);
+
+        // Board narrower than 5x5 wraps into itself
+        let wide = Topology { wrap: true, ..with(Neighbourhood::WIDE) };
+        assert_eq!(around(wide, 3, 3, 1, 1).len(), 8);
+
+        // Hexagons keep their own neighbours
+        assert_eq!(around(Topology { neighbourhood: Neighbourhood::KNIGHT, ..Topology::HEX }, 5, 5, 2, 2).len(), 6);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for shape in [Shape::Square, Shape::Hex] {
+            for wrap in [false, true] {
+                for neighbourhood in Neighbourhood::ALL {
+                    let topology = Topology { shape, wrap, neighbourhood };
+                    assert_eq!(Topology::from_name(&topology.to_string()), Some(topology));
+                }
+            }
+        }
+        let knight = Topology { wrap: true, neighbourhood: Neighbourhood::KNIGHT, ..Topology::SQUARE };
+        assert_eq!(knight.to_string(), "square knight wrap");
+        assert_eq!(Topology::HEX.to_string(), "hex");
+        assert_eq!(Topology::from_name("triangle"), None);
+        assert_eq!(Topology::from_name("square twist"), None);
+    }
+
+    #[test]
+    fn any_offsets_make_neighbourhood() {
+        // Only cells above and below, two rows away as well
+        let column = Neighbourhood::new(&[(-2, 0), (-1, 0), (1, 0), (2, 0)]);
+        let topology = Topology { neighbourhood: column, ..Topology::SQUARE };
+        assert_eq!(around(topology, 5, 5, 2, 2), [(0, 2), (1, 2), (3, 2), (4, 2)]);
+
+        // Name is the list of offsets, presets are
+        // recognized by theirs and keep short names
+        assert_eq!(topology.to_string(), "square offsets:-2,0/-1,0/1,0/2,0");
+        assert_eq!(Topology::from_name(&topology.to_string()), Some(topology));
+        let knight = Neighbourhood::new(&KNIGHT);
+        assert_eq!(Topology { neighbourhood: knight, ..Topology::SQUARE }.to_string(), "square knight");
+
+        assert_eq!(Topology::from_name("square offsets:"), None);
+        assert_eq!(Topology::from_name("square offsets:1,x"), None);
+        assert_eq!(Topology::from_name(&format!("square offsets:{}", ["1,1"; 25].join("/"))), None);
+    }
+}